@@ -0,0 +1,80 @@
+use super::{Pipeline, RunPipelineBody};
+use crate::client::{ApiClient, Result};
+use crate::config::HttpConfig;
+use async_trait::async_trait;
+
+#[async_trait]
+#[cfg_attr(test, mockall::automock)]
+pub trait BitbucketClient {
+    async fn pipeline_info(
+        &self,
+        workspace: &str,
+        repo: &str,
+        build_number: u64,
+    ) -> Result<Pipeline>;
+    async fn run_pipeline(
+        &self,
+        workspace: &str,
+        repo: &str,
+        body: &RunPipelineBody,
+    ) -> Result<Pipeline>;
+}
+
+pub struct DefaultBitbucketClient {
+    client: ApiClient,
+}
+
+impl DefaultBitbucketClient {
+    const API_BASE: &'static str = "https://api.bitbucket.org/2.0";
+
+    pub fn new<U: Into<String>, P: Into<String>>(
+        username: U,
+        app_password: P,
+        http_config: &HttpConfig,
+    ) -> Self {
+        Self {
+            client: ApiClient::from_credentials(username, app_password, http_config),
+        }
+    }
+
+    /// Creates a client that authenticates using a repository, project or workspace access token
+    pub fn from_access_token<T: Into<String>>(access_token: T, http_config: &HttpConfig) -> Self {
+        Self {
+            client: ApiClient::from_token(access_token, http_config),
+        }
+    }
+}
+
+#[async_trait]
+impl BitbucketClient for DefaultBitbucketClient {
+    async fn pipeline_info(
+        &self,
+        workspace: &str,
+        repo: &str,
+        build_number: u64,
+    ) -> Result<Pipeline> {
+        let url = format!(
+            "{}/repositories/{}/{}/pipelines/{}",
+            Self::API_BASE,
+            workspace,
+            repo,
+            build_number
+        );
+        self.client.get(&url).await
+    }
+
+    async fn run_pipeline(
+        &self,
+        workspace: &str,
+        repo: &str,
+        body: &RunPipelineBody,
+    ) -> Result<Pipeline> {
+        let url = format!(
+            "{}/repositories/{}/{}/pipelines/",
+            Self::API_BASE,
+            workspace,
+            repo
+        );
+        self.client.post(&url, body).await
+    }
+}
@@ -0,0 +1,7 @@
+pub mod client;
+pub mod models;
+pub mod workflows;
+
+pub use client::*;
+pub use models::*;
+pub use workflows::BitbucketPipelinesWorkflowRunner;
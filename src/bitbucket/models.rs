@@ -0,0 +1,16 @@
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub uuid: String,
+    pub build_number: u64,
+
+    // The target is only ever sent back when re-running a pipeline so there's no point in
+    // modelling every kind of target bitbucket supports.
+    pub target: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct RunPipelineBody {
+    pub target: serde_json::Value,
+}
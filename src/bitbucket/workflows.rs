@@ -0,0 +1,213 @@
+use super::{BitbucketClient, RunPipelineBody};
+use crate::processing::Error;
+use crate::processing::{FailedWorkflow, FailedWorkflows, WorkflowRunner};
+use async_trait::async_trait;
+use reqwest::Url;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+pub struct BitbucketPipelinesWorkflowRunner<C> {
+    client: Arc<C>,
+}
+
+impl<C: BitbucketClient> BitbucketPipelinesWorkflowRunner<C> {
+    pub fn new(client: Arc<C>) -> Self {
+        Self { client }
+    }
+
+    fn parse_pipeline_url(url: &Url, routed: bool) -> PipelineUrl<'_> {
+        if !routed && url.domain() != Some("bitbucket.org") {
+            return PipelineUrl::Unrelated;
+        }
+        let segments: Vec<_> = match url.path_segments() {
+            Some(segments) => segments.collect(),
+            None => return PipelineUrl::Unrelated,
+        };
+        if segments.len() != 5 || segments[2] != "pipelines" || segments[3] != "results" {
+            return PipelineUrl::Unrelated;
+        }
+        match segments[4].parse() {
+            Ok(build_number) => PipelineUrl::Pipeline {
+                workspace: segments[0],
+                repo: segments[1],
+                build_number,
+            },
+            Err(_) => PipelineUrl::Unrelated,
+        }
+    }
+}
+
+#[async_trait]
+impl<C: BitbucketClient + Send + Sync> WorkflowRunner for BitbucketPipelinesWorkflowRunner<C> {
+    fn workflow_kind(&self) -> &'static str {
+        "Bitbucket pipeline"
+    }
+
+    async fn find_failed_workflows(
+        &self,
+        job_urls: &[Url],
+        routed: bool,
+    ) -> Result<FailedWorkflows, Error> {
+        let mut failed_workflows = FailedWorkflows::default();
+        let mut failed_pipelines = HashSet::new();
+        for job_url in job_urls {
+            if let PipelineUrl::Pipeline {
+                workspace,
+                repo,
+                build_number,
+            } = Self::parse_pipeline_url(job_url, routed)
+            {
+                if failed_pipelines.insert((workspace, repo, build_number)) {
+                    failed_workflows.rerunnable.push(FailedWorkflow {
+                        id: build_number.to_string(),
+                        name: format!("{}/{} #{}", workspace, repo, build_number),
+                        job_url: job_url.clone(),
+                    });
+                }
+            }
+        }
+        Ok(failed_workflows)
+    }
+
+    async fn rerun_workflow(&self, workflow: &FailedWorkflow) -> Result<(), Error> {
+        // The URL was already matched when the workflow was found
+        let (workspace, repo, build_number) =
+            match Self::parse_pipeline_url(&workflow.job_url, true) {
+                PipelineUrl::Pipeline {
+                    workspace,
+                    repo,
+                    build_number,
+                } => (workspace, repo, build_number),
+                PipelineUrl::Unrelated => return Err(Error::as_generic("invalid URL")),
+            };
+        let pipeline = self
+            .client
+            .pipeline_info(workspace, repo, build_number)
+            .await?;
+        let body = RunPipelineBody {
+            target: pipeline.target,
+        };
+        self.client.run_pipeline(workspace, repo, &body).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PipelineUrl<'a> {
+    Pipeline {
+        workspace: &'a str,
+        repo: &'a str,
+        build_number: u64,
+    },
+    Unrelated,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitbucket::{MockBitbucketClient, Pipeline};
+    use serde_json::json;
+    use std::future;
+
+    type Runner = BitbucketPipelinesWorkflowRunner<MockBitbucketClient>;
+
+    fn make_pipeline(build_number: u64) -> Pipeline {
+        Pipeline {
+            uuid: format!("{{{}}}", build_number),
+            build_number,
+            target: json!({"type": "pipeline_ref_target", "ref_name": "potato"}),
+        }
+    }
+
+    #[test]
+    fn test_parse_pipeline_url() {
+        let url = Url::parse("https://bitbucket.org/acme/potato/pipelines/results/42").unwrap();
+        assert_eq!(
+            Runner::parse_pipeline_url(&url, false),
+            PipelineUrl::Pipeline {
+                workspace: "acme",
+                repo: "potato",
+                build_number: 42
+            }
+        );
+
+        let url = Url::parse("https://circleci.com/gh/acme/potato/42").unwrap();
+        assert_eq!(
+            Runner::parse_pipeline_url(&url, false),
+            PipelineUrl::Unrelated
+        );
+
+        let url = Url::parse("https://bitbucket.org/acme/potato/pull-requests/42").unwrap();
+        assert_eq!(
+            Runner::parse_pipeline_url(&url, false),
+            PipelineUrl::Unrelated
+        );
+
+        let url = Url::parse("https://bitbucket.org/acme/potato/pipelines/results/x").unwrap();
+        assert_eq!(
+            Runner::parse_pipeline_url(&url, false),
+            PipelineUrl::Unrelated
+        );
+
+        let url = Url::parse("https://ci-proxy.acme.com/acme/potato/pipelines/results/42").unwrap();
+        assert_eq!(
+            Runner::parse_pipeline_url(&url, true),
+            PipelineUrl::Pipeline {
+                workspace: "acme",
+                repo: "potato",
+                build_number: 42
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_failed_workflows() {
+        let runner = Runner::new(Arc::new(MockBitbucketClient::default()));
+        let urls = [
+            Url::parse("https://bitbucket.org/acme/potato/pipelines/results/42").unwrap(),
+            Url::parse("https://bitbucket.org/acme/potato/pipelines/results/42").unwrap(),
+            Url::parse("https://bitbucket.org/acme/potato/pull-requests/1").unwrap(),
+            Url::parse("https://circleci.com/gh/acme/potato/42").unwrap(),
+        ];
+        let workflows = runner.find_failed_workflows(&urls, false).await.unwrap();
+        assert_eq!(
+            workflows,
+            FailedWorkflows {
+                rerunnable: vec![FailedWorkflow {
+                    id: "42".into(),
+                    name: "acme/potato #42".into(),
+                    job_url: urls[0].clone(),
+                }],
+                skipped: false,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rerun_workflow() {
+        let mut client = MockBitbucketClient::default();
+        client
+            .expect_pipeline_info()
+            .withf(|workspace, repo, build_number| {
+                workspace == "acme" && repo == "potato" && *build_number == 42
+            })
+            .times(1)
+            .returning(|_, _, build_number| {
+                Box::pin(future::ready(Ok(make_pipeline(build_number))))
+            });
+        client
+            .expect_run_pipeline()
+            .withf(|workspace, repo, body| {
+                workspace == "acme" && repo == "potato" && body.target == make_pipeline(42).target
+            })
+            .times(1)
+            .returning(|_, _, _| Box::pin(future::ready(Ok(make_pipeline(43)))));
+        let runner = Runner::new(Arc::new(client));
+        let workflow = FailedWorkflow {
+            id: "42".into(),
+            name: "acme/potato #42".into(),
+            job_url: Url::parse("https://bitbucket.org/acme/potato/pipelines/results/42").unwrap(),
+        };
+        runner.rerun_workflow(&workflow).await.unwrap();
+    }
+}
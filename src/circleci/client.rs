@@ -1,5 +1,6 @@
 use super::{Job, NoBody};
 use crate::client::{ApiClient, Result};
+use crate::config::HttpConfig;
 use async_trait::async_trait;
 use serde_derive::Serialize;
 
@@ -16,9 +17,9 @@ pub struct DefaultCircleCiClient {
 impl DefaultCircleCiClient {
     const API_BASE: &'static str = "https://circleci.com/api/v2";
 
-    pub fn new<U: Into<String>>(username: U) -> Self {
+    pub fn new<U: Into<String>>(username: U, http_config: &HttpConfig) -> Self {
         Self {
-            client: ApiClient::from_username(username),
+            client: ApiClient::from_username(username, http_config),
         }
     }
 }
@@ -10,6 +10,7 @@ pub struct Job {
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct WorkflowSummary {
     pub id: String,
+    pub name: String,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq)]
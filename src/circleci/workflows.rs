@@ -1,23 +1,28 @@
 use super::CircleCiClient;
+use crate::config::RerunFilterConfig;
 use crate::processing::Error;
-use crate::processing::{WorkflowRunner, WorkflowStatus};
+use crate::processing::{FailedWorkflow, FailedWorkflows, WorkflowRunner};
 use async_trait::async_trait;
-use log::info;
+use log::warn;
 use reqwest::Url;
 use std::collections::HashSet;
 use std::sync::Arc;
 
 pub struct CircleCiWorkflowRunner<C> {
     client: Arc<C>,
+    rerun_filter: RerunFilterConfig,
 }
 
 impl<C: CircleCiClient> CircleCiWorkflowRunner<C> {
-    pub fn new(client: Arc<C>) -> Self {
-        Self { client }
+    pub fn new(client: Arc<C>, rerun_filter: RerunFilterConfig) -> Self {
+        Self {
+            client,
+            rerun_filter,
+        }
     }
 
-    fn parse_job_url(url: &Url) -> Result<JobUrl, Error> {
-        if url.domain() != Some("circleci.com") {
+    fn parse_job_url(url: &Url, routed: bool) -> Result<JobUrl<'_>, Error> {
+        if !routed && url.domain() != Some("circleci.com") {
             return Ok(JobUrl::Unrelated);
         }
         let segments: Vec<_> = url
@@ -40,10 +45,20 @@ impl<C: CircleCiClient> CircleCiWorkflowRunner<C> {
 
 #[async_trait]
 impl<C: CircleCiClient + Send + Sync> WorkflowRunner for CircleCiWorkflowRunner<C> {
-    async fn process_failed_jobs(&self, job_urls: &[Url]) -> Result<WorkflowStatus, Error> {
+    fn workflow_kind(&self) -> &'static str {
+        "CircleCI workflow"
+    }
+
+    async fn find_failed_workflows(
+        &self,
+        job_urls: &[Url],
+        routed: bool,
+    ) -> Result<FailedWorkflows, Error> {
+        let mut failed_workflows = FailedWorkflows::default();
         let mut failed_workflow_ids = HashSet::new();
+        let mut skipped_workflows = HashSet::new();
         for job_url in job_urls {
-            let (owner, repo, job_id) = match Self::parse_job_url(job_url)? {
+            let (owner, repo, job_id) = match Self::parse_job_url(job_url, routed)? {
                 JobUrl::Job {
                     owner,
                     repo,
@@ -52,19 +67,29 @@ impl<C: CircleCiClient + Send + Sync> WorkflowRunner for CircleCiWorkflowRunner<
                 JobUrl::Unrelated => continue,
             };
             let job_info = self.client.job_info(owner, repo, job_id).await?;
-            failed_workflow_ids.insert(job_info.latest_workflow.id);
-        }
-        if failed_workflow_ids.is_empty() {
-            return Ok(WorkflowStatus::Success);
+            let workflow = job_info.latest_workflow;
+            if self.rerun_filter.allows(&workflow.name) {
+                if failed_workflow_ids.insert(workflow.id.clone()) {
+                    failed_workflows.rerunnable.push(FailedWorkflow {
+                        id: workflow.id,
+                        name: workflow.name,
+                        job_url: job_url.clone(),
+                    });
+                }
+            } else if skipped_workflows.insert(workflow.id) {
+                warn!(
+                    "Not re-running circleci workflow '{}' as it's not allowed to be re-ran",
+                    workflow.name
+                );
+            }
         }
-        info!(
-            "Re-running {} failed circleci workflows",
-            failed_workflow_ids.len()
-        );
-        for workflow_id in failed_workflow_ids {
-            self.client.rerun_workflow(&workflow_id).await?;
-        }
-        Ok(WorkflowStatus::Triggered)
+        failed_workflows.skipped = !skipped_workflows.is_empty();
+        Ok(failed_workflows)
+    }
+
+    async fn rerun_workflow(&self, workflow: &FailedWorkflow) -> Result<(), Error> {
+        self.client.rerun_workflow(&workflow.id).await?;
+        Ok(())
     }
 }
 
@@ -1,47 +1,90 @@
+use crate::config::HttpConfig;
 use backoff::{backoff::Backoff, ExponentialBackoff};
+use chrono::{DateTime, TimeZone, Utc};
 use log::info;
-use reqwest::{Client, ClientBuilder, RequestBuilder, StatusCode};
+use reqwest::{header::HeaderMap, Client, ClientBuilder, RequestBuilder, StatusCode};
 use serde::{de::DeserializeOwned, Serialize};
+use serde_derive::Deserialize;
 use std::fmt::Debug;
 use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use thiserror::Error;
 use tokio::time::sleep;
 
-static USER_AGENT: &str = "mergebro";
-
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Clone)]
 pub struct ApiClient {
     client: Client,
-    username: String,
-    password: Option<String>,
+    credentials: Credentials,
+    rate_limit: RateLimitTracker,
+}
+
+#[derive(Clone)]
+enum Credentials {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
 }
 
 impl ApiClient {
-    pub fn from_username<S: Into<String>>(username: S) -> Self {
-        ApiClient::new(username.into(), None)
+    pub fn from_username<S: Into<String>>(username: S, config: &HttpConfig) -> Self {
+        let credentials = Credentials::Basic {
+            username: username.into(),
+            password: None,
+        };
+        ApiClient::new(credentials, config)
+    }
+
+    pub fn from_credentials<U: Into<String>, P: Into<String>>(
+        username: U,
+        password: P,
+        config: &HttpConfig,
+    ) -> Self {
+        let credentials = Credentials::Basic {
+            username: username.into(),
+            password: Some(password.into()),
+        };
+        ApiClient::new(credentials, config)
     }
 
-    pub fn from_credentials<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
-        ApiClient::new(username.into(), Some(password.into()))
+    pub fn from_token<T: Into<String>>(token: T, config: &HttpConfig) -> Self {
+        ApiClient::new(Credentials::Bearer(token.into()), config)
     }
 
-    fn new(username: String, password: Option<String>) -> Self {
-        let client = ClientBuilder::new().user_agent(USER_AGENT).build().unwrap();
+    fn new(credentials: Credentials, config: &HttpConfig) -> Self {
+        let client = ClientBuilder::new()
+            .user_agent(&config.user_agent)
+            .build()
+            .unwrap();
         Self {
             client,
-            username,
-            password,
+            credentials,
+            rate_limit: RateLimitTracker::new(config.rate_limit_floor),
         }
     }
 
+    /// The rate limit tracker, shared by every clone of this client
+    pub fn rate_limit(&self) -> &RateLimitTracker {
+        &self.rate_limit
+    }
+
     pub async fn get<O>(&self, endpoint: &str) -> Result<O>
     where
         O: DeserializeOwned + Debug,
     {
-        retry_request_if_needed(|| {
-            let builder = self.client.get(endpoint);
+        self.get_with_headers(endpoint, &HeaderMap::new()).await
+    }
+
+    pub async fn get_with_headers<O>(&self, endpoint: &str, headers: &HeaderMap) -> Result<O>
+    where
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(&self.rate_limit, || {
+            let builder = self.client.get(endpoint).headers(headers.clone());
             self.submit(builder)
         })
         .await
@@ -52,8 +95,26 @@ impl ApiClient {
         I: Serialize,
         O: DeserializeOwned + Debug,
     {
-        retry_request_if_needed(|| {
-            let builder = self.client.post(endpoint).json(body);
+        self.post_with_headers(endpoint, body, &HeaderMap::new())
+            .await
+    }
+
+    pub async fn post_with_headers<I, O>(
+        &self,
+        endpoint: &str,
+        body: &I,
+        headers: &HeaderMap,
+    ) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(&self.rate_limit, || {
+            let builder = self
+                .client
+                .post(endpoint)
+                .headers(headers.clone())
+                .json(body);
             self.submit(builder)
         })
         .await
@@ -64,8 +125,38 @@ impl ApiClient {
         I: Serialize,
         O: DeserializeOwned + Debug,
     {
-        retry_request_if_needed(|| {
-            let builder = self.client.put(endpoint).json(body);
+        self.put_with_headers(endpoint, body, &HeaderMap::new())
+            .await
+    }
+
+    pub async fn put_with_headers<I, O>(
+        &self,
+        endpoint: &str,
+        body: &I,
+        headers: &HeaderMap,
+    ) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(&self.rate_limit, || {
+            let builder = self
+                .client
+                .put(endpoint)
+                .headers(headers.clone())
+                .json(body);
+            self.submit(builder)
+        })
+        .await
+    }
+
+    pub async fn patch<I, O>(&self, endpoint: &str, body: &I) -> Result<O>
+    where
+        I: Serialize,
+        O: DeserializeOwned + Debug,
+    {
+        retry_request_if_needed(&self.rate_limit, || {
+            let builder = self.client.patch(endpoint).json(body);
             self.submit(builder)
         })
         .await
@@ -75,14 +166,107 @@ impl ApiClient {
     where
         O: DeserializeOwned,
     {
-        let builder = builder.basic_auth(&self.username, self.password.as_ref());
+        let builder = match &self.credentials {
+            Credentials::Basic { username, password } => {
+                builder.basic_auth(username, password.as_ref())
+            }
+            Credentials::Bearer(token) => builder.bearer_auth(token),
+        };
         let response = builder.send().await?;
-        if response.status().is_success() {
+        self.rate_limit.update(response.headers());
+        let status = response.status();
+        if status.is_success() {
             Ok(response.json().await?)
         } else {
-            Err(Error::Http(response.status()))
+            let body = response.text().await.unwrap_or_default();
+            Err(Error::Http {
+                status,
+                message: parse_error_message(body),
+            })
+        }
+    }
+}
+
+/// The rate limit state as last reported by the server
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateLimit {
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+}
+
+/// Keeps track of the rate limit reported via `X-RateLimit-*` response headers
+#[derive(Clone, Default)]
+pub struct RateLimitTracker {
+    floor: Option<u32>,
+    current: Arc<Mutex<Option<RateLimit>>>,
+}
+
+impl RateLimitTracker {
+    pub fn new(floor: Option<u32>) -> Self {
+        Self {
+            floor,
+            current: Arc::default(),
+        }
+    }
+
+    /// The last known rate limit state, if the server reported it
+    pub fn current(&self) -> Option<RateLimit> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// How long to wait for the rate limit to reset, if fewer requests than the floor are left
+    pub fn throttle_delay(&self) -> Option<Duration> {
+        let floor = self.floor?;
+        match self.current() {
+            Some(rate_limit) if rate_limit.remaining < floor => Self::delay_until(rate_limit.reset),
+            _ => None,
+        }
+    }
+
+    /// Whether the last response said there's no requests left
+    fn exhausted(&self) -> bool {
+        matches!(self.current(), Some(rate_limit) if rate_limit.remaining == 0)
+    }
+
+    /// How long to wait for the rate limit to reset, if it was exhausted
+    fn exhausted_delay(&self) -> Option<Duration> {
+        match self.current() {
+            Some(rate_limit) if rate_limit.remaining == 0 => Self::delay_until(rate_limit.reset),
+            _ => None,
+        }
+    }
+
+    fn delay_until(reset: DateTime<Utc>) -> Option<Duration> {
+        (reset - Utc::now()).to_std().ok()
+    }
+
+    fn update(&self, headers: &HeaderMap) {
+        let remaining = Self::parse_header(headers, "x-ratelimit-remaining");
+        let reset = Self::parse_header(headers, "x-ratelimit-reset")
+            .and_then(|reset| Utc.timestamp_opt(reset, 0).single());
+        if let (Some(remaining), Some(reset)) = (remaining, reset) {
+            *self.current.lock().unwrap() = Some(RateLimit {
+                remaining: remaining as u32,
+                reset,
+            });
         }
     }
+
+    fn parse_header(headers: &HeaderMap, name: &str) -> Option<i64> {
+        headers.get(name)?.to_str().ok()?.parse().ok()
+    }
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+fn parse_error_message(body: String) -> String {
+    match serde_json::from_str::<ErrorBody>(&body) {
+        Ok(error) => error.message,
+        Err(_) => body,
+    }
 }
 
 #[derive(Error, Debug)]
@@ -90,8 +274,11 @@ pub enum Error {
     #[error("rate limited max attempts reached")]
     RateLimitRetries,
 
-    #[error("request failed with status code {0}")]
-    Http(StatusCode),
+    #[error("request failed with status code {status}: {message}")]
+    Http { status: StatusCode, message: String },
+
+    #[error("graphql request failed: {0}")]
+    GraphQl(String),
 
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
@@ -99,27 +286,56 @@ pub enum Error {
 
 impl Error {
     pub fn not_found(&self) -> bool {
-        matches!(self, Self::Http(StatusCode::NOT_FOUND))
+        self.status() == Some(StatusCode::NOT_FOUND)
     }
 
     pub fn unprocessable_entity(&self) -> bool {
-        matches!(self, Self::Http(StatusCode::UNPROCESSABLE_ENTITY))
+        self.status() == Some(StatusCode::UNPROCESSABLE_ENTITY)
     }
 
     pub fn method_not_allowed(&self) -> bool {
-        matches!(self, Self::Http(StatusCode::METHOD_NOT_ALLOWED))
+        self.status() == Some(StatusCode::METHOD_NOT_ALLOWED)
     }
 
     pub fn too_many_requests(&self) -> bool {
-        matches!(self, Self::Http(StatusCode::TOO_MANY_REQUESTS))
+        self.status() == Some(StatusCode::TOO_MANY_REQUESTS)
+    }
+
+    pub fn forbidden(&self) -> bool {
+        self.status() == Some(StatusCode::FORBIDDEN)
     }
 
     pub fn conflict(&self) -> bool {
-        matches!(self, Self::Http(StatusCode::CONFLICT))
+        self.status() == Some(StatusCode::CONFLICT)
     }
+
+    /// GitHub rejects merges with this when the base branch changed while merging
+    pub fn base_branch_modified(&self) -> bool {
+        match self {
+            Self::Http { status, message } => {
+                matches!(
+                    *status,
+                    StatusCode::METHOD_NOT_ALLOWED | StatusCode::UNPROCESSABLE_ENTITY
+                ) && message.starts_with("Base branch was modified")
+            }
+            _ => false,
+        }
+    }
+
+    fn status(&self) -> Option<StatusCode> {
+        match self {
+            Self::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+/// GitHub reports an exhausted primary rate limit as a 403 rather than a 429
+fn is_rate_limited(error: &Error, rate_limit: &RateLimitTracker) -> bool {
+    error.too_many_requests() || (error.forbidden() && rate_limit.exhausted())
 }
 
-async fn retry_request_if_needed<F, R, O>(requestor: F) -> Result<O>
+async fn retry_request_if_needed<F, R, O>(rate_limit: &RateLimitTracker, requestor: F) -> Result<O>
 where
     F: Fn() -> R,
     R: Future<Output = Result<O>>,
@@ -129,8 +345,11 @@ where
     let mut backoff = ExponentialBackoff::default();
     loop {
         match requestor().await {
-            Err(e) if e.too_many_requests() => {
-                let delay = backoff.next_backoff();
+            Err(e) if is_rate_limited(&e, rate_limit) => {
+                // Wait until the rate limit resets if we know when, rather than guessing
+                let delay = backoff
+                    .next_backoff()
+                    .map(|delay| rate_limit.exhausted_delay().unwrap_or(delay));
                 match delay {
                     Some(delay) => {
                         info!("Rate limit hit, sleeping for {}s", delay.as_secs());
@@ -143,3 +362,106 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[test]
+    fn test_parse_error_message() {
+        let body = r#"{"message": "Base branch was modified. Review and try the merge again.", "documentation_url": "https://docs.github.com"}"#;
+        assert_eq!(
+            parse_error_message(body.into()),
+            "Base branch was modified. Review and try the merge again."
+        );
+        assert_eq!(parse_error_message("oops".into()), "oops");
+    }
+
+    #[test]
+    fn test_rate_limit_tracker() {
+        let tracker = RateLimitTracker::new(Some(100));
+        assert_eq!(tracker.throttle_delay(), None);
+
+        let reset = Utc::now().timestamp() + 600;
+        let mut headers = HeaderMap::new();
+        headers.insert("x-ratelimit-remaining", "500".parse().unwrap());
+        headers.insert("x-ratelimit-reset", reset.to_string().parse().unwrap());
+        tracker.update(&headers);
+        assert_eq!(tracker.current().map(|r| r.remaining), Some(500));
+        assert_eq!(tracker.throttle_delay(), None);
+
+        headers.insert("x-ratelimit-remaining", "50".parse().unwrap());
+        tracker.clone().update(&headers);
+        let delay = tracker.throttle_delay().unwrap();
+        assert!(delay > Duration::from_secs(590) && delay <= Duration::from_secs(600));
+        assert_eq!(tracker.exhausted_delay(), None);
+
+        // Without a floor, we never throttle
+        let tracker = RateLimitTracker::new(None);
+        tracker.update(&headers);
+        assert_eq!(tracker.throttle_delay(), None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_exhausted_rate_limit() {
+        let server = MockServer::start().await;
+        let reset = Utc::now().timestamp().to_string();
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-ratelimit-remaining", "0")
+                    .insert_header("x-ratelimit-reset", reset.as_str())
+                    .set_body_json(serde_json::json!({"message": "API rate limit exceeded"})),
+            )
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = ApiClient::from_username("potato", &HttpConfig::default());
+        let _: serde_json::Value = client.get(&server.uri()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_forbidden_not_retried() {
+        let server = MockServer::start().await;
+        let reset = Utc::now().timestamp().to_string();
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(403)
+                    .insert_header("x-ratelimit-remaining", "10")
+                    .insert_header("x-ratelimit-reset", reset.as_str()),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = ApiClient::from_username("potato", &HttpConfig::default());
+        let error = client
+            .get::<serde_json::Value>(&server.uri())
+            .await
+            .unwrap_err();
+        assert!(error.forbidden());
+    }
+
+    #[test]
+    fn test_base_branch_modified() {
+        let error = Error::Http {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            message: "Base branch was modified. Review and try the merge again.".into(),
+        };
+        assert!(error.base_branch_modified());
+        assert!(error.method_not_allowed());
+
+        let error = Error::Http {
+            status: StatusCode::METHOD_NOT_ALLOWED,
+            message: "Merge commits are not allowed on this repository.".into(),
+        };
+        assert!(!error.base_branch_modified());
+    }
+}
@@ -1,3 +1,4 @@
+use regex::Regex;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt;
@@ -130,10 +131,64 @@ pub enum RepoMapError {
     DuplicateRepo(RepoIdentifier),
 }
 
+/// A shell-like glob pattern where `*` matches any sequence of characters and `?` matches a
+/// single one
+#[derive(Debug, Clone)]
+pub struct GlobPattern {
+    pattern: String,
+    regex: Regex,
+}
+
+impl GlobPattern {
+    pub fn matches(&self, input: &str) -> bool {
+        self.regex.is_match(input)
+    }
+}
+
+impl fmt::Display for GlobPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.pattern)
+    }
+}
+
+impl FromStr for GlobPattern {
+    type Err = regex::Error;
+
+    fn from_str(s: &str) -> Result<Self, regex::Error> {
+        let mut regex = String::from("^");
+        for c in s.chars() {
+            match c {
+                '*' => regex.push_str(".*"),
+                '?' => regex.push('.'),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        regex.push('$');
+        Ok(Self {
+            pattern: s.into(),
+            regex: Regex::new(&regex)?,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_glob_pattern() {
+        let pattern: GlobPattern = "release/*".parse().unwrap();
+        assert!(pattern.matches("release/1.0"));
+        assert!(pattern.matches("release/"));
+        assert!(!pattern.matches("main"));
+        assert!(!pattern.matches("pre-release/1.0"));
+
+        let pattern: GlobPattern = "v?.x".parse().unwrap();
+        assert!(pattern.matches("v1.x"));
+        assert!(!pattern.matches("v10.x"));
+        assert!(!pattern.matches("v1-x"));
+    }
+
     #[test]
     fn test_repo_id_from_str() {
         assert_eq!(
@@ -1,6 +1,11 @@
 use crate::github::MergeMethod;
 use config::{Config, ConfigError, Environment, File};
+use regex::Regex;
+use serde::de::{self, Deserialize as _, Deserializer};
 use serde_derive::Deserialize;
+use std::env;
+use std::path::Path;
+use thiserror::Error;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct MergebroConfig {
@@ -12,12 +17,36 @@ pub struct MergebroConfig {
     #[serde(default)]
     pub poll: PollConfig,
 
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    #[serde(default)]
+    pub feedback: FeedbackConfig,
+
+    #[serde(default)]
+    pub multi: MultiConfig,
+
+    #[serde(default)]
+    pub command: CommandConfig,
+
+    #[serde(default)]
+    pub sweep: SweepConfig,
+
     #[serde(default)]
     pub workflows: WorkflowsConfig,
 
     #[serde(default = "default_reviews_config")]
     pub reviews: ReviewsConfig,
 
+    #[serde(default)]
+    pub behind: BehindConfig,
+
+    #[serde(default)]
+    pub statuses: StatusesConfig,
+
+    #[serde(default)]
+    pub current_state: CurrentStateConfig,
+
     #[serde(default)]
     pub repos: Vec<RepoConfig>,
 }
@@ -25,50 +54,301 @@ pub struct MergebroConfig {
 #[derive(Deserialize, Debug, Clone)]
 pub struct PollConfig {
     pub delay_seconds: u8,
+
+    /// The maximum amount of time to wait for a pull request to be merged before giving up
+    pub max_duration_seconds: Option<u64>,
 }
 
 impl Default for PollConfig {
     fn default() -> PollConfig {
-        PollConfig { delay_seconds: 30 }
+        PollConfig {
+            delay_seconds: 30,
+            max_duration_seconds: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct FeedbackConfig {
+    /// Whether to post a comment on the pull request when giving up on it after a timeout
+    #[serde(default)]
+    pub comment_on_timeout: bool,
+
+    /// A label to be added to the pull request when giving up on it after a timeout
+    pub timeout_label: Option<String>,
+
+    /// Whether to report mergebro's own progress as a commit status on the pull request's head
+    #[serde(default)]
+    pub own_status: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommandConfig {
+    /// Whether to wait until an authorized user comments the trigger command on the pull request
+    #[serde(default)]
+    pub required: bool,
+
+    #[serde(default = "default_command_trigger")]
+    pub trigger: String,
+
+    /// The users allowed to trigger the command
+    #[serde(default)]
+    pub authorized_users: Vec<String>,
+}
+
+impl Default for CommandConfig {
+    fn default() -> CommandConfig {
+        CommandConfig {
+            required: false,
+            trigger: default_command_trigger(),
+            authorized_users: Vec::new(),
+        }
+    }
+}
+
+fn default_command_trigger() -> String {
+    "/mergebro merge".into()
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct MultiConfig {
+    #[serde(default)]
+    pub on_error: OnErrorPolicy,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SweepConfig {
+    /// The search query used to find the pull requests to be processed on every sweep
+    pub query: Option<String>,
+
+    /// The number of seconds between sweeps
+    #[serde(default = "default_sweep_interval_seconds")]
+    pub interval_seconds: u64,
+
+    /// The maximum number of pull requests being processed at the same time
+    pub max_concurrent: Option<usize>,
+}
+
+impl Default for SweepConfig {
+    fn default() -> SweepConfig {
+        SweepConfig {
+            query: None,
+            interval_seconds: default_sweep_interval_seconds(),
+            max_concurrent: None,
+        }
+    }
+}
+
+fn default_sweep_interval_seconds() -> u64 {
+    60
+}
+
+/// Defines what happens with the rest of the pull requests when processing one of them fails
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum OnErrorPolicy {
+    #[serde(rename = "stop")]
+    Stop,
+
+    #[serde(rename = "continue")]
+    #[default]
+    Continue,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct CurrentStateConfig {
+    /// Whether to mark draft pull requests as ready for review once every other check passes
+    #[serde(default)]
+    pub mark_ready_when_passed: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct HttpConfig {
+    #[serde(default = "default_user_agent")]
+    pub user_agent: String,
+
+    /// Once fewer requests than this are left before hitting the rate limit, wait for it to reset
+    pub rate_limit_floor: Option<u32>,
+}
+
+impl Default for HttpConfig {
+    fn default() -> HttpConfig {
+        HttpConfig {
+            user_agent: default_user_agent(),
+            rate_limit_floor: None,
+        }
     }
 }
 
+fn default_user_agent() -> String {
+    concat!("mergebro/", env!("CARGO_PKG_VERSION")).into()
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct MergeConfig {
     pub default_method: MergeMethod,
+
+    /// Glob patterns for base branches that require an explicit opt-in to be merged into
+    #[serde(default)]
+    pub sensitive_base_branches: Vec<String>,
+
+    /// Whether to fetch the pull request after merging it to make sure it was actually merged
+    #[serde(default)]
+    pub verify_after: bool,
+
+    /// Whether to let GitHub merge pull requests that have native auto-merge enabled
+    #[serde(default = "default_defer_to_native_auto_merge")]
+    pub defer_to_native_auto_merge: bool,
+
+    /// Whether to disable GitHub's native auto-merge when mergebro gives up on a pull request
+    #[serde(default)]
+    pub disable_native_on_block: bool,
 }
 
 impl Default for MergeConfig {
     fn default() -> MergeConfig {
         MergeConfig {
             default_method: MergeMethod::Merge,
+            sensitive_base_branches: Vec::new(),
+            verify_after: false,
+            defer_to_native_auto_merge: default_defer_to_native_auto_merge(),
+            disable_native_on_block: false,
         }
     }
 }
 
+fn default_defer_to_native_auto_merge() -> bool {
+    true
+}
+
 #[derive(Deserialize, Debug, Clone)]
 pub struct GithubConfig {
     pub username: String,
     pub token: String,
+
+    /// The base URL for API requests, e.g. "https://github.example.com/api/v3" for GitHub Enterprise
+    #[serde(default = "default_github_endpoint")]
+    pub endpoint: String,
+
+    /// The hosts pull request URLs can point to, e.g. "github.example.com" for GitHub Enterprise
+    #[serde(default = "default_github_hosts")]
+    pub hosts: Vec<String>,
+}
+
+fn default_github_endpoint() -> String {
+    "https://api.github.com".into()
+}
+
+fn default_github_hosts() -> Vec<String> {
+    vec!["github.com".into()]
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
 pub struct WorkflowsConfig {
     pub circleci: Option<CircleCiConfig>,
+    pub bitbucket: Option<BitbucketConfig>,
+
+    #[serde(default)]
+    pub actions: ActionsConfig,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct ActionsConfig {
+    /// Glob patterns for names of Actions workflows whose failures don't block merging
+    #[serde(default)]
+    pub ignore: Vec<String>,
+}
+
+/// Workflow settings that can be overridden per repo
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct RepoWorkflowsConfig {
+    pub actions: Option<ActionsConfig>,
+
+    /// The workflow runners used to re-run this repo's failed status checks. All configured
+    /// runners are used if unset.
+    pub enabled: Option<Vec<WorkflowRunnerKind>>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct CircleCiConfig {
     pub token: String,
+
+    #[serde(flatten)]
+    pub rerun: RerunFilterConfig,
+}
+
+/// Defines which workflows can be re-ran when they fail
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct RerunFilterConfig {
+    /// If non empty, only these workflows will be re-ran
+    #[serde(default)]
+    pub rerunnable_workflows: Vec<String>,
+
+    /// These workflows will never be re-ran
+    #[serde(default)]
+    pub non_rerunnable_workflows: Vec<String>,
+}
+
+impl RerunFilterConfig {
+    pub fn allows(&self, workflow_name: &str) -> bool {
+        let name = workflow_name.to_string();
+        (self.rerunnable_workflows.is_empty() || self.rerunnable_workflows.contains(&name))
+            && !self.non_rerunnable_workflows.contains(&name)
+    }
+}
+
+/// Bitbucket credentials, either a username and app password or an access token
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum BitbucketConfig {
+    AppPassword { username: String, password: String },
+    AccessToken { access_token: String },
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct ReviewsConfig {
     pub approvals: u32,
+
+    #[serde(default)]
+    pub mode: ReviewsMode,
+
+    /// Whether to give up on a pull request rather than wait when it doesn't have enough approvals
+    #[serde(default)]
+    pub abort_on_shortfall: bool,
 }
 
 fn default_reviews_config() -> ReviewsConfig {
-    ReviewsConfig { approvals: 1 }
+    ReviewsConfig {
+        approvals: 1,
+        mode: ReviewsMode::default(),
+        abort_on_shortfall: false,
+    }
+}
+
+/// Defines where the number of required approvals comes from
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum ReviewsMode {
+    /// Use the highest between the branch protection rules and the configured approvals
+    #[serde(rename = "max")]
+    #[default]
+    Max,
+
+    /// Only honor the branch protection rules
+    #[serde(rename = "branch_protection_only")]
+    BranchProtectionOnly,
+
+    /// Only honor the configured approvals
+    #[serde(rename = "config_only")]
+    ConfigOnly,
+}
+
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct BehindConfig {
+    /// An extra ref, in the base repo, the pull request's branch must be up to date with. Pull
+    /// requests from forks can't be updated, so they're blocked if they fall behind it
+    pub compare_ref: Option<String>,
+
+    /// The maximum number of consecutive branch updates that can leave it still behind its base
+    pub max_update_attempts: Option<u32>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -77,8 +357,121 @@ pub struct RepoConfig {
 
     pub reviews: Option<ReviewsConfig>,
 
+    pub behind: Option<BehindConfig>,
+
     #[serde(default)]
     pub statuses: Vec<StatusConfig>,
+
+    pub version_bump: Option<VersionBumpConfig>,
+
+    pub commit_messages: Option<CommitMessagesConfig>,
+
+    pub task_list: Option<TaskListConfig>,
+
+    pub deployment: Option<DeploymentConfig>,
+
+    pub workflows: Option<RepoWorkflowsConfig>,
+
+    /// Commands to be executed as checks. Note that these run arbitrary user provided commands.
+    #[serde(default)]
+    pub external_commands: Vec<ExternalCommandConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExternalCommandConfig {
+    pub name: String,
+
+    /// The command to run using `sh -c`
+    pub command: String,
+
+    /// The exit code that signals the check should be retried later
+    #[serde(default = "default_waiting_exit_code")]
+    pub waiting_exit_code: i32,
+
+    /// The number of seconds after which the command is killed and the check fails
+    #[serde(default = "default_external_command_timeout_seconds")]
+    pub timeout_seconds: u64,
+}
+
+fn default_waiting_exit_code() -> i32 {
+    // EX_TEMPFAIL
+    75
+}
+
+fn default_external_command_timeout_seconds() -> u64 {
+    300
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TaskListConfig {
+    /// Wait until every task list item in the pull request's body is checked
+    #[serde(default)]
+    pub require_all_checked: bool,
+
+    /// The minimum number of task list items that need to be checked
+    #[serde(default)]
+    pub min_checked: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeploymentConfig {
+    /// The environment that must not have a deployment in progress before merging
+    pub environment: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CommitMessagesConfig {
+    /// A regex every commit message in the pull request must match
+    pub pattern: String,
+
+    /// Only check commit messages if the pull request won't be squashed, i.e. the default merge
+    /// method isn't squash or the repository doesn't allow squashing
+    #[serde(default)]
+    pub only_preserving_methods: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct VersionBumpConfig {
+    /// A regex with a single capture group used to extract the version bump from the title
+    #[serde(
+        default = "default_version_bump_pattern",
+        deserialize_with = "deserialize_capturing_regex"
+    )]
+    pub pattern: Regex,
+
+    /// A prefix for labels that declare the version bump, e.g. "bump:" for "bump:minor"
+    pub label_prefix: Option<String>,
+
+    #[serde(default = "default_version_bumps")]
+    pub allowed: Vec<String>,
+
+    /// Files that are expected to be modified whenever the version is bumped
+    #[serde(default)]
+    pub version_files: Vec<String>,
+}
+
+fn default_version_bump_pattern() -> Regex {
+    Regex::new(r"^\[(\w+)\]").expect("invalid default version bump pattern")
+}
+
+/// Parses a regex that must contain at least one capture group
+fn deserialize_capturing_regex<'de, D>(deserializer: D) -> Result<Regex, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let pattern = String::deserialize(deserializer)?;
+    let regex = Regex::new(&pattern).map_err(de::Error::custom)?;
+    if regex.captures_len() < 2 {
+        return Err(de::Error::custom(format!(
+            "pattern '{}' has no capture group",
+            pattern
+        )));
+    }
+    Ok(regex)
+}
+
+fn default_version_bumps() -> Vec<String> {
+    vec!["major".into(), "minor".into(), "patch".into()]
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -86,7 +479,77 @@ pub struct StatusConfig {
     pub name: String,
 
     #[serde(flatten)]
-    pub failures: StatusFailuresConfig,
+    pub failures: Option<StatusFailuresConfig>,
+
+    /// The runner used to re-run this status check when it fails, regardless of its URL
+    pub runner: Option<WorkflowRunnerKind>,
+}
+
+/// Runners are tried in the order they're declared here
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum WorkflowRunnerKind {
+    #[serde(rename = "circleci")]
+    CircleCi,
+
+    #[serde(rename = "bitbucket")]
+    Bitbucket,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct StatusesConfig {
+    #[serde(default)]
+    pub failure_reset: FailureResetPolicy,
+
+    #[serde(default = "default_failure_window_seconds")]
+    pub failure_window_seconds: u64,
+
+    /// Only re-trigger status checks and Actions workflows that have succeeded at least once on any
+    /// of the pull request's commits
+    #[serde(default)]
+    pub require_prior_success_to_retrigger: bool,
+
+    /// The amount of time a status check needs to be green for before it's considered passed
+    #[serde(default)]
+    pub green_stability_seconds: u64,
+
+    /// Whether to check statuses on the commit that merges the pull request into its base
+    /// rather than on its head
+    #[serde(default)]
+    pub check_merge_ref: bool,
+}
+
+impl Default for StatusesConfig {
+    fn default() -> StatusesConfig {
+        StatusesConfig {
+            failure_reset: FailureResetPolicy::default(),
+            failure_window_seconds: default_failure_window_seconds(),
+            require_prior_success_to_retrigger: false,
+            green_stability_seconds: 0,
+            check_merge_ref: false,
+        }
+    }
+}
+
+fn default_failure_window_seconds() -> u64 {
+    3600
+}
+
+/// Defines when the per status check failure counters are reset
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub enum FailureResetPolicy {
+    /// Reset counters whenever the pull request's head changes
+    #[serde(rename = "on_head_change")]
+    #[default]
+    OnHeadChange,
+
+    /// Reset counters whenever the head changes and also forget failures older than the
+    /// configured window
+    #[serde(rename = "time_decay")]
+    TimeDecay,
+
+    /// Never reset counters
+    #[serde(rename = "never")]
+    Never,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -95,11 +558,190 @@ pub struct StatusFailuresConfig {
 }
 
 impl MergebroConfig {
-    pub fn new(config_file_path: &str) -> Result<Self, ConfigError> {
+    /// Loads the configuration. An explicitly provided config file must exist, whereas the
+    /// default ones are optional as everything can be configured via environment variables.
+    pub fn new(config_file_path: Option<&str>) -> Result<Self, ConfigLoadError> {
+        let path = match config_file_path {
+            Some(path) => Some(shellexpand::tilde(path).into_owned()),
+            None => {
+                let xdg_config_home = env::var("XDG_CONFIG_HOME").ok();
+                Self::default_config_file_paths(xdg_config_home.as_deref())
+                    .into_iter()
+                    .find(|path| Path::new(path).exists())
+            }
+        };
+        Self::load(path.as_deref()).map_err(|e| ConfigLoadError::new(path, e))
+    }
+
+    fn load(path: Option<&str>) -> Result<Self, ConfigError> {
         let mut config = Config::new();
-        let config_file_path = shellexpand::tilde(config_file_path);
-        config.merge(File::with_name(&config_file_path).required(false))?;
+        if let Some(path) = path {
+            config.merge(File::with_name(path).required(true))?;
+        }
         config.merge(Environment::with_prefix("mergebro").separator("_"))?;
         config.try_into()
     }
+
+    /// The paths the config file is looked up in when none is provided, in order of precedence
+    fn default_config_file_paths(xdg_config_home: Option<&str>) -> Vec<String> {
+        let xdg_config_home = xdg_config_home.unwrap_or("~/.config");
+        let paths = [
+            format!("{}/mergebro/config.yaml", xdg_config_home),
+            "~/.mergebro/config.yaml".into(),
+            "./mergebro.yaml".into(),
+        ];
+        paths
+            .iter()
+            .map(|path| shellexpand::tilde(path).into_owned())
+            .collect()
+    }
+}
+
+/// An error loading the configuration, with enough context to know where to fix it
+#[derive(Error, Debug)]
+#[error("{source} ({location}){hint}")]
+pub struct ConfigLoadError {
+    location: String,
+    hint: String,
+    source: Box<ConfigError>,
+}
+
+impl ConfigLoadError {
+    /// The required keys that can also be set via environment variables
+    const ENV_KEYS: &'static [&'static str] = &["github.username", "github.token"];
+
+    fn new(path: Option<String>, source: ConfigError) -> Self {
+        let location = match path {
+            Some(path) => format!("using config file '{}'", path),
+            None => "no config file found, only using environment variables".into(),
+        };
+        let hint = Self::env_var_hint(&source.to_string())
+            .map(|env_var| {
+                format!(
+                    "; it can also be set via the {} environment variable",
+                    env_var
+                )
+            })
+            .unwrap_or_default();
+        Self {
+            location,
+            hint,
+            source: Box::new(source),
+        }
+    }
+
+    fn env_var_hint(message: &str) -> Option<String> {
+        let field = message.strip_prefix("missing field `")?.split('`').next()?;
+        let key = Self::ENV_KEYS
+            .iter()
+            .find(|key| key.rsplit('.').next() == Some(field))?;
+        Some(format!("MERGEBRO_{}", key.replace('.', "_").to_uppercase()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_load_error() {
+        let error = ConfigLoadError::new(
+            Some("/tmp/config.yaml".into()),
+            ConfigError::Message("missing field `token`".into()),
+        );
+        assert_eq!(
+            error.to_string(),
+            "missing field `token` (using config file '/tmp/config.yaml'); it can also be set via the MERGEBRO_GITHUB_TOKEN environment variable"
+        );
+
+        let error = ConfigLoadError::new(None, ConfigError::Message("invalid type".into()));
+        assert_eq!(
+            error.to_string(),
+            "invalid type (no config file found, only using environment variables)"
+        );
+    }
+
+    #[test]
+    fn test_version_bump_pattern() {
+        let parse = |pattern: &str| {
+            serde_json::from_value::<VersionBumpConfig>(serde_json::json!({ "pattern": pattern }))
+        };
+        assert!(parse(r"^(\w+):").is_ok());
+        assert!(parse(r"^\w+:").is_err());
+        assert!(parse(r"^(\w+:").is_err());
+
+        let config: VersionBumpConfig = serde_json::from_value(serde_json::json!({})).unwrap();
+        assert_eq!(config.pattern.as_str(), r"^\[(\w+)\]");
+    }
+
+    #[test]
+    fn test_default_config_file_paths() {
+        let home = shellexpand::tilde("~").into_owned();
+        assert_eq!(
+            MergebroConfig::default_config_file_paths(Some("/etc/xdg")),
+            vec![
+                "/etc/xdg/mergebro/config.yaml".to_string(),
+                format!("{}/.mergebro/config.yaml", home),
+                "./mergebro.yaml".to_string(),
+            ]
+        );
+        assert_eq!(
+            MergebroConfig::default_config_file_paths(None)[0],
+            format!("{}/.config/mergebro/config.yaml", home)
+        );
+    }
+
+    #[test]
+    fn test_rerun_filter() {
+        let filter = RerunFilterConfig::default();
+        assert!(filter.allows("build"));
+
+        let filter = RerunFilterConfig {
+            rerunnable_workflows: vec!["build".into(), "test".into()],
+            non_rerunnable_workflows: vec!["test".into()],
+        };
+        assert!(filter.allows("build"));
+        assert!(!filter.allows("test"));
+        assert!(!filter.allows("deploy"));
+    }
+
+    #[test]
+    fn test_status_config() {
+        let config: StatusConfig =
+            serde_json::from_str(r#"{"name": "ci", "runner": "circleci"}"#).unwrap();
+        assert!(config.failures.is_none());
+        assert_eq!(config.runner, Some(WorkflowRunnerKind::CircleCi));
+
+        let config: StatusConfig =
+            serde_json::from_str(r#"{"name": "ci", "max_failures": 2}"#).unwrap();
+        assert_eq!(config.failures.map(|f| f.max_failures), Some(2));
+        assert_eq!(config.runner, None);
+    }
+
+    #[test]
+    fn test_bitbucket_config() {
+        let config: BitbucketConfig =
+            serde_json::from_str(r#"{"username": "potato", "password": "secret"}"#).unwrap();
+        assert!(matches!(config, BitbucketConfig::AppPassword { .. }));
+
+        let config: BitbucketConfig =
+            serde_json::from_str(r#"{"access_token": "secret"}"#).unwrap();
+        assert!(matches!(config, BitbucketConfig::AccessToken { .. }));
+
+        assert!(serde_json::from_str::<BitbucketConfig>(r#"{"username": "potato"}"#).is_err());
+    }
+
+    #[test]
+    fn test_repo_workflows_config() {
+        let config: RepoWorkflowsConfig =
+            serde_json::from_str(r#"{"enabled": ["bitbucket"]}"#).unwrap();
+        assert_eq!(config.enabled, Some(vec![WorkflowRunnerKind::Bitbucket]));
+
+        let config: RepoWorkflowsConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.enabled, None);
+
+        assert!(
+            serde_json::from_str::<RepoWorkflowsConfig>(r#"{"enabled": ["jenkins"]}"#).is_err()
+        );
+    }
 }
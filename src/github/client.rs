@@ -1,30 +1,85 @@
 use super::models::{
-    ActionRuns, Branch, BranchProtection, NoBody, PullRequest, PullRequestIdentifier,
-    PullRequestReview, Repository, Status,
+    ActionRuns, Branch, BranchProtection, CheckRuns, CombinedStatus, Comment, Comparison,
+    Deployment, DeploymentStatus, GraphQlResponse, IssueSearchResults, Label, MergeResult, NoBody,
+    PullRequest, PullRequestCommit, PullRequestFile, PullRequestIdentifier, PullRequestReview,
+    Repository, RepositoryInfo, Status, StatusState, UpdateBranchMethod,
 };
-use crate::client::{ApiClient, Result};
+use crate::client::{ApiClient, Error, RateLimitTracker, Result};
+use crate::config::HttpConfig;
 use crate::github::MergeMethod;
 use async_trait::async_trait;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
 use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
 
 #[async_trait]
 #[cfg_attr(test, mockall::automock)]
 pub trait GithubClient: Send + Sync {
     async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest>;
+    async fn search_pull_requests(&self, query: &str) -> Result<IssueSearchResults>;
+    async fn repo_info(&self, owner: &str, repo: &str) -> Result<RepositoryInfo>;
     async fn pull_request_reviews(
         &self,
         pull_request: &PullRequest,
     ) -> Result<Vec<PullRequestReview>>;
     async fn pull_request_statuses(&self, pull_request: &PullRequest) -> Result<Vec<Status>>;
+    async fn combined_status(&self, pull_request: &PullRequest) -> Result<CombinedStatus>;
+    async fn pull_request_files(&self, pull_request: &PullRequest) -> Result<Vec<PullRequestFile>>;
+    async fn pull_request_commits(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<PullRequestCommit>>;
+    async fn check_runs(&self, pull_request: &PullRequest) -> Result<CheckRuns>;
+    async fn commit_statuses(&self, repo: &Repository, sha: &str) -> Result<Vec<Status>>;
+    async fn commit_check_runs(&self, repo: &Repository, sha: &str) -> Result<CheckRuns>;
     async fn branch_protection(&self, branch: &Branch) -> Result<BranchProtection>;
-    async fn update_branch(&self, pull_request: &PullRequest) -> Result<NoBody>;
+    async fn deployments(&self, repo: &Repository, environment: &str) -> Result<Vec<Deployment>>;
+    async fn deployment_statuses(
+        &self,
+        repo: &Repository,
+        deployment_id: u64,
+    ) -> Result<Vec<DeploymentStatus>>;
+    async fn update_branch(
+        &self,
+        pull_request: &PullRequest,
+        method: UpdateBranchMethod,
+    ) -> Result<NoBody>;
+    async fn compare_commits(
+        &self,
+        repo: &Repository,
+        base: &str,
+        head: &str,
+    ) -> Result<Comparison>;
+    async fn merge_into_branch(&self, branch: &Branch, head: &str) -> Result<NoBody>;
     async fn action_runs(&self, pull_request: &PullRequest) -> Result<ActionRuns>;
     async fn rerun_workflow(&self, repo: &Repository, run_id: u64) -> Result<NoBody>;
+    async fn mark_ready_for_review(&self, pull_request: &PullRequest) -> Result<()>;
+    async fn disable_auto_merge(&self, pull_request: &PullRequest) -> Result<()>;
+    async fn list_comments(&self, pull_request: &PullRequest) -> Result<Vec<Comment>>;
+    async fn create_comment(&self, pull_request: &PullRequest, body: &str) -> Result<Comment>;
+    async fn update_comment(
+        &self,
+        pull_request: &PullRequest,
+        comment_id: u64,
+        body: &str,
+    ) -> Result<Comment>;
+    async fn add_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>>;
+    async fn create_commit_status(
+        &self,
+        pull_request: &PullRequest,
+        context: &str,
+        state: StatusState,
+        description: &str,
+    ) -> Result<NoBody>;
     async fn merge_pull_request(
         &self,
         pull_request: &PullRequest,
         body: &MergeRequestBody,
-    ) -> Result<NoBody>; // TODO: add body
+    ) -> Result<MergeResult>;
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -39,23 +94,144 @@ pub struct MergeRequestBody {
 #[derive(Clone)]
 pub struct DefaultGithubClient {
     client: ApiClient,
+    api_base: String,
+    // Repository settings rarely change so they're only fetched once
+    repo_infos: Arc<Mutex<HashMap<String, RepositoryInfo>>>,
 }
 
 impl DefaultGithubClient {
-    const API_BASE: &'static str = "https://api.github.com";
+    pub const DEFAULT_API_BASE: &'static str = "https://api.github.com";
+    const PAGE_SIZE: usize = 100;
+    const UPDATE_BRANCH_MEDIA_TYPE: &'static str = "application/vnd.github.lydian-preview+json";
 
-    pub fn new<U: Into<String>, P: Into<String>>(username: U, password: P) -> Self {
+    pub fn new<U: Into<String>, P: Into<String>>(
+        username: U,
+        password: P,
+        http_config: &HttpConfig,
+    ) -> Self {
         Self {
-            client: ApiClient::from_credentials(username, password),
+            client: ApiClient::from_credentials(username, password, http_config),
+            api_base: Self::DEFAULT_API_BASE.into(),
+            repo_infos: Arc::default(),
+        }
+    }
+
+    /// Uses the given base URL for API requests, e.g. for GitHub Enterprise
+    pub fn with_api_base<S: Into<String>>(mut self, api_base: S) -> Self {
+        self.api_base = api_base.into();
+        self
+    }
+
+    /// Tracks the rate limit reported by GitHub across every request made by this client
+    pub fn rate_limit(&self) -> RateLimitTracker {
+        self.client.rate_limit().clone()
+    }
+
+    async fn get_all_pages<O>(&self, url: &str) -> Result<Vec<O>>
+    where
+        O: DeserializeOwned + Debug,
+    {
+        self.get_all_wrapped_pages(url, |items: Vec<O>| items).await
+    }
+
+    /// Like `get_all_pages`, for endpoints that wrap the items in each page in an object
+    async fn get_all_wrapped_pages<P, O>(
+        &self,
+        url: &str,
+        unwrap: fn(P) -> Vec<O>,
+    ) -> Result<Vec<O>>
+    where
+        P: DeserializeOwned + Debug,
+    {
+        let mut output = Vec::new();
+        for page in 1.. {
+            let page_url = format!("{}?per_page={}&page={}", url, Self::PAGE_SIZE, page);
+            let items = unwrap(self.client.get(&page_url).await?);
+            let total_items = items.len();
+            output.extend(items);
+            if total_items < Self::PAGE_SIZE {
+                break;
+            }
         }
+        Ok(output)
+    }
+
+    async fn graphql(&self, query: &'static str, variables: serde_json::Value) -> Result<()> {
+        let url = self.make_graphql_url();
+        let body = GraphQlRequest { query, variables };
+        let response: GraphQlResponse = self.client.post(&url, &body).await?;
+        match response.errors.into_iter().next() {
+            Some(error) => Err(Error::GraphQl(error.message)),
+            None => Ok(()),
+        }
+    }
+
+    fn make_accept_headers(media_type: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(media_type));
+        headers
+    }
+
+    fn make_graphql_url(&self) -> String {
+        // GitHub Enterprise serves REST under `<host>/api/v3` but GraphQL under `<host>/api`
+        let api_base = self.api_base.trim_end_matches('/');
+        let root = api_base.strip_suffix("/v3").unwrap_or(api_base);
+        format!("{}/graphql", root)
+    }
+
+    fn make_search_url(&self, query: &str, page: usize) -> String {
+        let url = format!("{}/search/issues", self.api_base);
+        let mut query = query.to_string();
+        // Only pull requests can be processed, but the query may already say so
+        for qualifier in ["is:pr", "is:open"] {
+            if !query.split_whitespace().any(|term| term == qualifier) {
+                query = format!("{} {}", query, qualifier);
+            }
+        }
+        let params = [
+            ("q", query),
+            ("per_page", Self::PAGE_SIZE.to_string()),
+            ("page", page.to_string()),
+        ];
+        // Both the base URL and the parameters are known to be valid
+        Url::parse_with_params(&url, &params)
+            .expect("invalid search URL")
+            .into()
+    }
+
+    fn make_deployments_url(&self, repo: &Repository, environment: &str) -> String {
+        let url = format!("{}/repos/{}/deployments", self.api_base, repo.full_name);
+        // Both the base URL and the parameters are known to be valid
+        Url::parse_with_params(&url, &[("environment", environment)])
+            .expect("invalid deployments URL")
+            .into()
+    }
+
+    fn make_compare_url(&self, repo: &Repository, base: &str, head: &str) -> String {
+        format!(
+            "{}/repos/{}/compare/{}...{}",
+            self.api_base, repo.full_name, base, head
+        )
+    }
+
+    fn make_statuses_url(&self, pull_request: &PullRequest) -> String {
+        format!(
+            "{}/repos/{}/statuses/{}",
+            self.api_base, pull_request.base.repo.full_name, pull_request.head.sha
+        )
     }
 
-    fn make_pull_request_url(pull_request: &PullRequest) -> String {
+    fn make_issue_url(&self, pull_request: &PullRequest) -> String {
+        format!(
+            "{}/repos/{}/issues/{}",
+            self.api_base, pull_request.base.repo.full_name, pull_request.number
+        )
+    }
+
+    fn make_pull_request_url(&self, pull_request: &PullRequest) -> String {
         format!(
             "{}/repos/{}/pulls/{}",
-            Self::API_BASE,
-            pull_request.base.repo.full_name,
-            pull_request.number
+            self.api_base, pull_request.base.repo.full_name, pull_request.number
         )
     }
 }
@@ -65,52 +241,173 @@ impl GithubClient for DefaultGithubClient {
     async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest> {
         let url = format!(
             "{}/repos/{}/{}/pulls/{}",
-            Self::API_BASE,
-            id.owner,
-            id.repo,
-            id.pull_number
+            self.api_base, id.owner, id.repo, id.pull_number
         );
         self.client.get(&url).await
     }
 
+    async fn search_pull_requests(&self, query: &str) -> Result<IssueSearchResults> {
+        let mut results = IssueSearchResults::default();
+        for page in 1.. {
+            let url = self.make_search_url(query, page);
+            let page_results: IssueSearchResults = self.client.get(&url).await?;
+            let total_items = page_results.items.len();
+            results.total_count = page_results.total_count;
+            results.items.extend(page_results.items);
+            if total_items < Self::PAGE_SIZE || results.items.len() >= results.total_count as usize
+            {
+                break;
+            }
+        }
+        Ok(results)
+    }
+
+    async fn repo_info(&self, owner: &str, repo: &str) -> Result<RepositoryInfo> {
+        let full_name = format!("{}/{}", owner, repo);
+        if let Some(info) = self.repo_infos.lock().unwrap().get(&full_name) {
+            return Ok(info.clone());
+        }
+        let url = format!("{}/repos/{}", self.api_base, full_name);
+        let info: RepositoryInfo = self.client.get(&url).await?;
+        self.repo_infos
+            .lock()
+            .unwrap()
+            .insert(full_name, info.clone());
+        Ok(info)
+    }
+
     async fn pull_request_reviews(
         &self,
         pull_request: &PullRequest,
     ) -> Result<Vec<PullRequestReview>> {
-        let url = format!("{}/reviews", Self::make_pull_request_url(pull_request));
+        let url = format!("{}/reviews", self.make_pull_request_url(pull_request));
         self.client.get(&url).await
     }
 
     async fn pull_request_statuses(&self, pull_request: &PullRequest) -> Result<Vec<Status>> {
-        self.client.get(&pull_request.links.statuses).await
+        match &pull_request.links.statuses {
+            Some(url) => self.client.get(url).await,
+            None => self.client.get(&self.make_statuses_url(pull_request)).await,
+        }
+    }
+
+    async fn combined_status(&self, pull_request: &PullRequest) -> Result<CombinedStatus> {
+        let url = format!(
+            "{}/repos/{}/commits/{}/status",
+            self.api_base, pull_request.base.repo.full_name, pull_request.head.sha
+        );
+        self.client.get(&url).await
+    }
+
+    async fn pull_request_files(&self, pull_request: &PullRequest) -> Result<Vec<PullRequestFile>> {
+        let url = format!("{}/files", self.make_pull_request_url(pull_request));
+        self.get_all_pages(&url).await
+    }
+
+    async fn pull_request_commits(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<PullRequestCommit>> {
+        let url = format!("{}/commits", self.make_pull_request_url(pull_request));
+        self.get_all_pages(&url).await
+    }
+
+    async fn check_runs(&self, pull_request: &PullRequest) -> Result<CheckRuns> {
+        self.commit_check_runs(&pull_request.base.repo, &pull_request.head.sha)
+            .await
+    }
+
+    async fn commit_statuses(&self, repo: &Repository, sha: &str) -> Result<Vec<Status>> {
+        let url = format!(
+            "{}/repos/{}/commits/{}/statuses",
+            self.api_base, repo.full_name, sha
+        );
+        self.get_all_pages(&url).await
+    }
+
+    async fn commit_check_runs(&self, repo: &Repository, sha: &str) -> Result<CheckRuns> {
+        let url = format!(
+            "{}/repos/{}/commits/{}/check-runs",
+            self.api_base, repo.full_name, sha
+        );
+        let check_runs = self
+            .get_all_wrapped_pages(&url, |page: CheckRuns| page.check_runs)
+            .await?;
+        Ok(CheckRuns { check_runs })
     }
 
     async fn branch_protection(&self, branch: &Branch) -> Result<BranchProtection> {
         let url = format!(
             "{}/repos/{}/{}/branches/{}/protection",
-            Self::API_BASE,
-            branch.user.login,
-            branch.repo.name,
-            branch.name,
+            self.api_base, branch.user.login, branch.repo.name, branch.name,
         );
         self.client.get(&url).await
     }
 
-    async fn update_branch(&self, pull_request: &PullRequest) -> Result<NoBody> {
+    async fn deployments(&self, repo: &Repository, environment: &str) -> Result<Vec<Deployment>> {
+        // Deployments are returned newest first, so the first page is all we care about
+        let url = self.make_deployments_url(repo, environment);
+        self.client.get(&url).await
+    }
+
+    async fn deployment_statuses(
+        &self,
+        repo: &Repository,
+        deployment_id: u64,
+    ) -> Result<Vec<DeploymentStatus>> {
         let url = format!(
-            "{}/update-branch",
-            Self::make_pull_request_url(pull_request)
+            "{}/repos/{}/deployments/{}/statuses",
+            self.api_base, repo.full_name, deployment_id
         );
+        self.client.get(&url).await
+    }
+
+    async fn update_branch(
+        &self,
+        pull_request: &PullRequest,
+        method: UpdateBranchMethod,
+    ) -> Result<NoBody> {
+        if method == UpdateBranchMethod::Rebase {
+            // The REST endpoint can only merge the base in, rebasing is only exposed via GraphQL
+            let query = "mutation($id: ID!, $sha: GitObjectID) { updatePullRequestBranch(input: {pullRequestId: $id, expectedHeadOid: $sha, updateMethod: REBASE}) { clientMutationId } }";
+            let variables = serde_json::json!({
+                "id": pull_request.node_id,
+                "sha": pull_request.head.sha,
+            });
+            self.graphql(query, variables).await?;
+            return Ok(NoBody {});
+        }
+        let url = format!("{}/update-branch", self.make_pull_request_url(pull_request));
         let body = UpdateBranchRequest {
             expected_head_sha: pull_request.head.sha.clone(),
         };
-        self.client.put(&url, &body).await
+        let headers = Self::make_accept_headers(Self::UPDATE_BRANCH_MEDIA_TYPE);
+        self.client.put_with_headers(&url, &body, &headers).await
+    }
+
+    async fn compare_commits(
+        &self,
+        repo: &Repository,
+        base: &str,
+        head: &str,
+    ) -> Result<Comparison> {
+        let url = self.make_compare_url(repo, base, head);
+        self.client.get(&url).await
+    }
+
+    async fn merge_into_branch(&self, branch: &Branch, head: &str) -> Result<NoBody> {
+        let url = format!("{}/repos/{}/merges", self.api_base, branch.repo.full_name);
+        let body = MergeBranchRequest {
+            base: &branch.name,
+            head,
+        };
+        self.client.post(&url, &body).await
     }
 
     async fn action_runs(&self, pull_request: &PullRequest) -> Result<ActionRuns> {
         let url = format!(
             "{}/repos/{}/{}/actions/runs?branch={}&actor={}",
-            Self::API_BASE,
+            self.api_base,
             pull_request.base.repo.owner.login,
             pull_request.base.repo.name,
             pull_request.head.name,
@@ -122,20 +419,78 @@ impl GithubClient for DefaultGithubClient {
     async fn rerun_workflow(&self, repo: &Repository, run_id: u64) -> Result<NoBody> {
         let url = format!(
             "{}/repos/{}/{}/actions/runs/{}/rerun",
-            Self::API_BASE,
-            repo.owner.login,
-            repo.name,
-            run_id,
+            self.api_base, repo.owner.login, repo.name, run_id,
         );
         self.client.post(&url, &()).await
     }
 
+    async fn mark_ready_for_review(&self, pull_request: &PullRequest) -> Result<()> {
+        let query = "mutation($id: ID!) { markPullRequestReadyForReview(input: {pullRequestId: $id}) { clientMutationId } }";
+        let variables = serde_json::json!({ "id": pull_request.node_id });
+        self.graphql(query, variables).await
+    }
+
+    async fn disable_auto_merge(&self, pull_request: &PullRequest) -> Result<()> {
+        let query = "mutation($id: ID!) { disablePullRequestAutoMerge(input: {pullRequestId: $id}) { clientMutationId } }";
+        let variables = serde_json::json!({ "id": pull_request.node_id });
+        self.graphql(query, variables).await
+    }
+
+    async fn list_comments(&self, pull_request: &PullRequest) -> Result<Vec<Comment>> {
+        let url = format!("{}/comments", self.make_issue_url(pull_request));
+        self.get_all_pages(&url).await
+    }
+
+    async fn create_comment(&self, pull_request: &PullRequest, body: &str) -> Result<Comment> {
+        let url = format!("{}/comments", self.make_issue_url(pull_request));
+        let body = CreateCommentRequest { body };
+        self.client.post(&url, &body).await
+    }
+
+    async fn update_comment(
+        &self,
+        pull_request: &PullRequest,
+        comment_id: u64,
+        body: &str,
+    ) -> Result<Comment> {
+        let url = format!(
+            "{}/repos/{}/issues/comments/{}",
+            self.api_base, pull_request.base.repo.full_name, comment_id
+        );
+        let body = CreateCommentRequest { body };
+        self.client.patch(&url, &body).await
+    }
+
+    async fn add_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>> {
+        let url = format!("{}/labels", self.make_issue_url(pull_request));
+        let body = AddLabelsRequest {
+            labels: vec![label],
+        };
+        self.client.post(&url, &body).await
+    }
+
+    async fn create_commit_status(
+        &self,
+        pull_request: &PullRequest,
+        context: &str,
+        state: StatusState,
+        description: &str,
+    ) -> Result<NoBody> {
+        let url = self.make_statuses_url(pull_request);
+        let body = CreateStatusRequest {
+            state,
+            context,
+            description,
+        };
+        self.client.post(&url, &body).await
+    }
+
     async fn merge_pull_request(
         &self,
         pull_request: &PullRequest,
         body: &MergeRequestBody,
-    ) -> Result<NoBody> {
-        let url = format!("{}/merge", Self::make_pull_request_url(pull_request));
+    ) -> Result<MergeResult> {
+        let url = format!("{}/merge", self.make_pull_request_url(pull_request));
         self.client.put(&url, body).await
     }
 }
@@ -144,3 +499,196 @@ impl GithubClient for DefaultGithubClient {
 struct UpdateBranchRequest {
     expected_head_sha: String,
 }
+
+#[derive(Serialize, Debug, PartialEq)]
+struct MergeBranchRequest<'a> {
+    base: &'a str,
+    head: &'a str,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CreateCommentRequest<'a> {
+    body: &'a str,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct CreateStatusRequest<'a> {
+    state: StatusState,
+    context: &'a str,
+    description: &'a str,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct AddLabelsRequest<'a> {
+    labels: Vec<&'a str>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct GraphQlRequest {
+    query: &'static str,
+    variables: serde_json::Value,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn make_client() -> DefaultGithubClient {
+        DefaultGithubClient::new("potato", "token", &HttpConfig::default())
+    }
+
+    #[test]
+    fn test_make_compare_url() {
+        let repo = Repository {
+            full_name: "potato/smasher".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            make_client().make_compare_url(&repo, "main", "abc123"),
+            "https://api.github.com/repos/potato/smasher/compare/main...abc123"
+        );
+    }
+
+    #[test]
+    fn test_make_deployments_url() {
+        let repo = Repository {
+            full_name: "potato/smasher".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            make_client().make_deployments_url(&repo, "production eu"),
+            "https://api.github.com/repos/potato/smasher/deployments?environment=production+eu"
+        );
+    }
+
+    #[rstest]
+    #[case::github("https://api.github.com", "https://api.github.com/graphql")]
+    #[case::enterprise("https://ghe.potato.com/api/v3", "https://ghe.potato.com/api/graphql")]
+    #[case::trailing_slash("https://ghe.potato.com/api/v3/", "https://ghe.potato.com/api/graphql")]
+    fn test_make_graphql_url(#[case] api_base: &str, #[case] expected: &str) {
+        let client = make_client().with_api_base(api_base);
+        assert_eq!(client.make_graphql_url(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_update_comment() {
+        let server = MockServer::start().await;
+        Mock::given(method("PATCH"))
+            .and(path("/repos/potato/smasher/issues/comments/42"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": 42,
+                "user": {"login": "potato"},
+                "body": "updated",
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = make_client().with_api_base(server.uri());
+        let mut pull_request = PullRequest::default();
+        pull_request.base.repo.full_name = "potato/smasher".into();
+        let comment = client
+            .update_comment(&pull_request, 42, "updated")
+            .await
+            .unwrap();
+        assert_eq!(comment.body, "updated");
+    }
+
+    #[tokio::test]
+    async fn test_graphql_enterprise_endpoint() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/graphql"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({"data": {}})))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = make_client().with_api_base(format!("{}/api/v3", server.uri()));
+        let pull_request = PullRequest::default();
+        client.disable_auto_merge(&pull_request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_check_runs_paginated() {
+        let server = MockServer::start().await;
+        let make_page = |count: usize| {
+            let check_runs: Vec<_> = (0..count)
+                .map(|index| serde_json::json!({"name": format!("check {}", index)}))
+                .collect();
+            serde_json::json!({"total_count": 150, "check_runs": check_runs})
+        };
+        Mock::given(method("GET"))
+            .and(path("/repos/potato/smasher/commits/abc123/check-runs"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(make_page(100)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/potato/smasher/commits/abc123/check-runs"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(make_page(50)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = make_client().with_api_base(server.uri());
+        let repo = Repository {
+            full_name: "potato/smasher".into(),
+            ..Default::default()
+        };
+        let check_runs = client.commit_check_runs(&repo, "abc123").await.unwrap();
+        assert_eq!(check_runs.check_runs.len(), 150);
+    }
+
+    #[test]
+    fn test_make_search_url() {
+        assert_eq!(
+            make_client().make_search_url("head:my-branch repo:potato/smasher", 2),
+            "https://api.github.com/search/issues?q=head%3Amy-branch+repo%3Apotato%2Fsmasher+is%3Apr+is%3Aopen&per_page=100&page=2"
+        );
+        assert_eq!(
+            make_client().make_search_url("is:open is:pr label:automerge", 1),
+            "https://api.github.com/search/issues?q=is%3Aopen+is%3Apr+label%3Aautomerge&per_page=100&page=1"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_search_pull_requests_paginated() {
+        let server = MockServer::start().await;
+        let make_page = |first: u32, count: u32| {
+            let items: Vec<_> = (first..first + count)
+                .map(|number| {
+                    serde_json::json!({
+                        "number": number,
+                        "html_url": format!("https://github.com/potato/smasher/pull/{}", number),
+                    })
+                })
+                .collect();
+            serde_json::json!({"total_count": 130, "items": items})
+        };
+        Mock::given(method("GET"))
+            .and(path("/search/issues"))
+            .and(query_param("page", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(make_page(0, 100)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/search/issues"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(make_page(100, 30)))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let client = make_client().with_api_base(server.uri());
+        let results = client
+            .search_pull_requests("label:automerge")
+            .await
+            .unwrap();
+        assert_eq!(results.total_count, 130);
+        assert_eq!(results.items.len(), 130);
+        assert_eq!(results.items[129].number, 129);
+    }
+}
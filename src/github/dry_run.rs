@@ -0,0 +1,193 @@
+use super::client::{GithubClient, MergeRequestBody};
+use super::models::{
+    ActionRuns, Branch, BranchProtection, CheckRuns, CombinedStatus, Comment, Comparison,
+    Deployment, DeploymentStatus, IssueSearchResults, Label, MergeResult, NoBody, PullRequest,
+    PullRequestCommit, PullRequestFile, PullRequestIdentifier, PullRequestReview, Repository,
+    RepositoryInfo, Status, StatusState, UpdateBranchMethod,
+};
+use crate::client::Result;
+use async_trait::async_trait;
+use log::info;
+use std::sync::Arc;
+
+/// A github client that performs every read only request but only logs mutating ones
+pub struct DryRunGithubClient {
+    inner: Arc<dyn GithubClient>,
+}
+
+impl DryRunGithubClient {
+    pub fn new(inner: Arc<dyn GithubClient>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl GithubClient for DryRunGithubClient {
+    async fn pull_request_info(&self, id: &PullRequestIdentifier) -> Result<PullRequest> {
+        self.inner.pull_request_info(id).await
+    }
+
+    async fn search_pull_requests(&self, query: &str) -> Result<IssueSearchResults> {
+        self.inner.search_pull_requests(query).await
+    }
+
+    async fn repo_info(&self, owner: &str, repo: &str) -> Result<RepositoryInfo> {
+        self.inner.repo_info(owner, repo).await
+    }
+
+    async fn pull_request_reviews(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<PullRequestReview>> {
+        self.inner.pull_request_reviews(pull_request).await
+    }
+
+    async fn pull_request_statuses(&self, pull_request: &PullRequest) -> Result<Vec<Status>> {
+        self.inner.pull_request_statuses(pull_request).await
+    }
+
+    async fn combined_status(&self, pull_request: &PullRequest) -> Result<CombinedStatus> {
+        self.inner.combined_status(pull_request).await
+    }
+
+    async fn pull_request_files(&self, pull_request: &PullRequest) -> Result<Vec<PullRequestFile>> {
+        self.inner.pull_request_files(pull_request).await
+    }
+
+    async fn pull_request_commits(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Vec<PullRequestCommit>> {
+        self.inner.pull_request_commits(pull_request).await
+    }
+
+    async fn check_runs(&self, pull_request: &PullRequest) -> Result<CheckRuns> {
+        self.inner.check_runs(pull_request).await
+    }
+
+    async fn commit_statuses(&self, repo: &Repository, sha: &str) -> Result<Vec<Status>> {
+        self.inner.commit_statuses(repo, sha).await
+    }
+
+    async fn commit_check_runs(&self, repo: &Repository, sha: &str) -> Result<CheckRuns> {
+        self.inner.commit_check_runs(repo, sha).await
+    }
+
+    async fn deployments(&self, repo: &Repository, environment: &str) -> Result<Vec<Deployment>> {
+        self.inner.deployments(repo, environment).await
+    }
+
+    async fn deployment_statuses(
+        &self,
+        repo: &Repository,
+        deployment_id: u64,
+    ) -> Result<Vec<DeploymentStatus>> {
+        self.inner.deployment_statuses(repo, deployment_id).await
+    }
+
+    async fn branch_protection(&self, branch: &Branch) -> Result<BranchProtection> {
+        self.inner.branch_protection(branch).await
+    }
+
+    async fn update_branch(
+        &self,
+        pull_request: &PullRequest,
+        method: UpdateBranchMethod,
+    ) -> Result<NoBody> {
+        info!(
+            "Dry run: not updating branch '{}' with its base using {:?}",
+            pull_request.head.name, method
+        );
+        Ok(NoBody {})
+    }
+
+    async fn compare_commits(
+        &self,
+        repo: &Repository,
+        base: &str,
+        head: &str,
+    ) -> Result<Comparison> {
+        self.inner.compare_commits(repo, base, head).await
+    }
+
+    async fn merge_into_branch(&self, branch: &Branch, head: &str) -> Result<NoBody> {
+        info!("Dry run: not merging '{}' into '{}'", head, branch.name);
+        Ok(NoBody {})
+    }
+
+    async fn action_runs(&self, pull_request: &PullRequest) -> Result<ActionRuns> {
+        self.inner.action_runs(pull_request).await
+    }
+
+    async fn rerun_workflow(&self, _repo: &Repository, run_id: u64) -> Result<NoBody> {
+        info!("Dry run: not re-running actions workflow run {}", run_id);
+        Ok(NoBody {})
+    }
+
+    async fn mark_ready_for_review(&self, _pull_request: &PullRequest) -> Result<()> {
+        info!("Dry run: not marking pull request as ready for review");
+        Ok(())
+    }
+
+    async fn disable_auto_merge(&self, _pull_request: &PullRequest) -> Result<()> {
+        info!("Dry run: not disabling native auto-merge");
+        Ok(())
+    }
+
+    async fn list_comments(&self, pull_request: &PullRequest) -> Result<Vec<Comment>> {
+        self.inner.list_comments(pull_request).await
+    }
+
+    async fn create_comment(&self, _pull_request: &PullRequest, body: &str) -> Result<Comment> {
+        info!("Dry run: not commenting on pull request: {}", body);
+        Ok(Comment {
+            body: body.into(),
+            ..Default::default()
+        })
+    }
+
+    async fn update_comment(
+        &self,
+        _pull_request: &PullRequest,
+        comment_id: u64,
+        body: &str,
+    ) -> Result<Comment> {
+        info!("Dry run: not updating comment {}: {}", comment_id, body);
+        Ok(Comment {
+            id: comment_id,
+            body: body.into(),
+            ..Default::default()
+        })
+    }
+
+    async fn create_commit_status(
+        &self,
+        _pull_request: &PullRequest,
+        context: &str,
+        state: StatusState,
+        description: &str,
+    ) -> Result<NoBody> {
+        info!(
+            "Dry run: not setting commit status '{}' to {:?}: {}",
+            context, state, description
+        );
+        Ok(NoBody {})
+    }
+
+    async fn add_label(&self, pull_request: &PullRequest, label: &str) -> Result<Vec<Label>> {
+        info!("Dry run: not adding label '{}' to pull request", label);
+        Ok(pull_request.labels.clone())
+    }
+
+    async fn merge_pull_request(
+        &self,
+        _pull_request: &PullRequest,
+        body: &MergeRequestBody,
+    ) -> Result<MergeResult> {
+        info!(
+            "Dry run: not merging pull request using '{:?}' merge method",
+            body.merge_method
+        );
+        Ok(MergeResult::default())
+    }
+}
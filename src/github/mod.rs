@@ -1,5 +1,7 @@
 pub mod client;
+pub mod dry_run;
 pub mod models;
 
 pub use client::*;
+pub use dry_run::DryRunGithubClient;
 pub use models::*;
@@ -1,9 +1,11 @@
 use reqwest::Url;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
 use std::ops::Deref;
+use std::str::FromStr;
 use thiserror::Error;
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum MergeableState {
     #[serde(rename = "behind")]
     Behind,
@@ -21,16 +23,11 @@ pub enum MergeableState {
     Dirty,
 
     #[serde(other, rename = "unknown")]
+    #[default]
     Unknown,
 }
 
-impl Default for MergeableState {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum PullRequestState {
     #[serde(rename = "open")]
     Open,
@@ -39,15 +36,10 @@ pub enum PullRequestState {
     Closed,
 
     #[serde(other)]
+    #[default]
     Unknown,
 }
 
-impl Default for PullRequestState {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Link {
     href: String,
@@ -63,7 +55,8 @@ impl Deref for Link {
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Links {
-    pub statuses: Link,
+    #[serde(default)]
+    pub statuses: Option<Link>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -93,7 +86,8 @@ pub enum ReviewState {
 pub struct PullRequestReview {
     pub user: User,
     pub state: ReviewState,
-    pub submitted_at: chrono::DateTime<chrono::Local>,
+    /// Pending reviews haven't been submitted yet so they don't have a timestamp
+    pub submitted_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -101,6 +95,40 @@ pub struct Repository {
     pub name: String,
     pub owner: User,
     pub full_name: String,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+/// Repository level settings
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RepositoryInfo {
+    pub full_name: String,
+    pub default_branch: String,
+    #[serde(default)]
+    pub allow_squash_merge: bool,
+    #[serde(default)]
+    pub allow_merge_commit: bool,
+    #[serde(default)]
+    pub allow_rebase_merge: bool,
+    #[serde(default)]
+    pub delete_branch_on_merge: bool,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+impl RepositoryInfo {
+    /// Whether the given merge method can be used. The merge settings are only visible with push
+    /// access, so every method is assumed to be allowed if none of them are.
+    pub fn allows_merge_method(&self, method: &MergeMethod) -> bool {
+        if !self.allow_squash_merge && !self.allow_merge_commit && !self.allow_rebase_merge {
+            return true;
+        }
+        match method {
+            MergeMethod::Squash => self.allow_squash_merge,
+            MergeMethod::Merge => self.allow_merge_commit,
+            MergeMethod::Rebase => self.allow_rebase_merge,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -118,7 +146,7 @@ pub struct Branch {
 pub struct PullRequest {
     pub mergeable_state: MergeableState,
 
-    #[serde(rename = "_links")]
+    #[serde(rename = "_links", default)]
     pub links: Links,
 
     #[serde(rename = "user")]
@@ -132,12 +160,99 @@ pub struct PullRequest {
     pub draft: bool,
     pub body: Option<String>,
     pub number: u32,
+
+    #[serde(default)]
+    pub node_id: String,
+
+    #[serde(default)]
+    pub labels: Vec<Label>,
+
+    /// Set if GitHub's native auto-merge is enabled
+    #[serde(default)]
+    pub auto_merge: Option<AutoMerge>,
+
+    /// The SHA of the test merge commit, only set once GitHub computed it
+    #[serde(default)]
+    pub merge_commit_sha: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct AutoMerge {
+    pub enabled_by: User,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Label {
+    pub name: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Comparison {
+    pub ahead_by: u32,
+    pub behind_by: u32,
+    pub merge_base_commit: CommitSummary,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CommitSummary {
+    pub sha: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IssueSearchResults {
+    pub total_count: u32,
+    pub items: Vec<IssueSearchItem>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct IssueSearchItem {
+    pub number: u32,
+    pub html_url: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Comment {
+    pub id: u64,
+    pub user: User,
+    pub body: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PullRequestCommit {
+    pub sha: String,
+    pub commit: CommitDetails,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CommitDetails {
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PullRequestFile {
+    pub filename: String,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct BranchProtection {
-    #[serde(rename = "required_pull_request_reviews")]
+    #[serde(rename = "required_pull_request_reviews", default)]
     pub reviews: BranchProtectionReviews,
+
+    pub required_status_checks: Option<RequiredStatusChecks>,
+
+    #[serde(default)]
+    pub required_linear_history: ProtectionSetting,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ProtectionSetting {
+    pub enabled: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RequiredStatusChecks {
+    #[serde(default)]
+    pub contexts: Vec<String>,
 }
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Default)]
@@ -146,7 +261,9 @@ pub struct BranchProtectionReviews {
     pub approvals: u32,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// Serialized using its canonical `owner/repo#number` form
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash)]
+#[serde(into = "String", try_from = "String")]
 pub struct PullRequestIdentifier {
     pub owner: String,
     pub repo: String,
@@ -154,8 +271,9 @@ pub struct PullRequestIdentifier {
 }
 
 impl PullRequestIdentifier {
-    pub fn from_app_url(url: &Url) -> Result<Self, InvalidUrlError> {
-        if url.domain() != Some("github.com") {
+    pub fn from_app_url(url: &Url, web_hosts: &[String]) -> Result<Self, InvalidUrlError> {
+        let domain = url.domain().ok_or(InvalidUrlError::InvalidDomain)?;
+        if !web_hosts.iter().any(|host| host == domain) {
             return Err(InvalidUrlError::InvalidDomain);
         }
         let path_parts: Vec<_> = url
@@ -177,6 +295,58 @@ impl PullRequestIdentifier {
     }
 }
 
+impl fmt::Display for PullRequestIdentifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}/{}#{}", self.owner, self.repo, self.pull_number)
+    }
+}
+
+impl FromStr for PullRequestIdentifier {
+    type Err = MalformedPullRequestIdentifierError;
+
+    fn from_str(s: &str) -> Result<Self, MalformedPullRequestIdentifierError> {
+        let (repo_name, pull_number) =
+            s.split_once('#')
+                .ok_or(MalformedPullRequestIdentifierError(
+                    "missing pull request number",
+                ))?;
+        let (owner, repo) = repo_name
+            .split_once('/')
+            .ok_or(MalformedPullRequestIdentifierError("missing repo name"))?;
+        if owner.is_empty() || repo.is_empty() || repo.contains('/') {
+            return Err(MalformedPullRequestIdentifierError(
+                "invalid owner/repo name",
+            ));
+        }
+        let pull_number = pull_number
+            .parse()
+            .map_err(|_| MalformedPullRequestIdentifierError("invalid pull request number"))?;
+        Ok(Self {
+            owner: owner.into(),
+            repo: repo.into(),
+            pull_number,
+        })
+    }
+}
+
+impl From<PullRequestIdentifier> for String {
+    fn from(identifier: PullRequestIdentifier) -> Self {
+        identifier.to_string()
+    }
+}
+
+impl TryFrom<String> for PullRequestIdentifier {
+    type Error = MalformedPullRequestIdentifierError;
+
+    fn try_from(s: String) -> Result<Self, MalformedPullRequestIdentifierError> {
+        s.parse()
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Clone)]
+#[error("malformed pull request identifier: {0}")]
+pub struct MalformedPullRequestIdentifierError(&'static str);
+
 #[derive(Error, Debug, PartialEq, Clone)]
 pub enum InvalidUrlError {
     #[error("invalid domain")]
@@ -201,7 +371,7 @@ pub struct WorkflowRun {
     pub conclusion: Option<WorkflowRunConclusion>,
 }
 
-#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum WorfklowRunStatus {
     #[serde(rename = "completed")]
     Completed,
@@ -213,15 +383,10 @@ pub enum WorfklowRunStatus {
     InProgress,
 
     #[serde(other)]
+    #[default]
     Unknown,
 }
 
-impl Default for WorfklowRunStatus {
-    fn default() -> Self {
-        Self::Unknown
-    }
-}
-
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub enum WorkflowRunConclusion {
     #[serde(rename = "success")]
@@ -238,11 +403,74 @@ pub enum WorkflowRunConclusion {
 pub struct Status {
     pub target_url: String,
     pub state: StatusState,
-    pub created_at: chrono::DateTime<chrono::Local>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
     pub context: String,
 }
 
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CombinedStatus {
+    pub state: StatusState,
+    pub total_count: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Deployment {
+    pub id: u64,
+    pub sha: String,
+    pub environment: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct DeploymentStatus {
+    pub state: DeploymentState,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub enum DeploymentState {
+    #[serde(rename = "queued")]
+    Queued,
+
+    #[serde(rename = "pending")]
+    Pending,
+
+    #[serde(rename = "in_progress")]
+    InProgress,
+
+    #[serde(rename = "success")]
+    Success,
+
+    #[serde(rename = "failure")]
+    Failure,
+
+    #[serde(rename = "error")]
+    Error,
+
+    #[serde(rename = "inactive")]
+    Inactive,
+
+    #[serde(other)]
+    Unknown,
+}
+
+impl DeploymentState {
+    /// Whether a deployment in this state hasn't finished yet
+    pub fn is_active(&self) -> bool {
+        matches!(self, Self::Queued | Self::Pending | Self::InProgress)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct CheckRuns {
+    pub check_runs: Vec<CheckRun>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct CheckRun {
+    pub name: String,
+    pub conclusion: Option<WorkflowRunConclusion>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub enum StatusState {
     #[serde(rename = "success")]
     Success,
@@ -254,6 +482,7 @@ pub enum StatusState {
     Pending,
 
     #[serde(other)]
+    #[default]
     Unknown,
 }
 
@@ -269,34 +498,242 @@ pub enum MergeMethod {
     Rebase,
 }
 
+/// The way a pull request's branch is brought up to date with its base
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateBranchMethod {
+    Merge,
+    Rebase,
+}
+
 #[derive(Deserialize, Debug, Clone, PartialEq)]
 pub struct NoBody {}
 
+#[derive(Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct MergeResult {
+    pub sha: String,
+    pub merged: bool,
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GraphQlResponse {
+    #[serde(default)]
+    pub errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+pub struct GraphQlError {
+    pub message: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::TimeZone;
+
+    fn parse_app_url(
+        url: &str,
+        web_hosts: &[&str],
+    ) -> Result<PullRequestIdentifier, InvalidUrlError> {
+        let web_hosts: Vec<String> = web_hosts.iter().map(|host| host.to_string()).collect();
+        PullRequestIdentifier::from_app_url(&Url::parse(url).unwrap(), &web_hosts)
+    }
 
     #[test]
     fn pull_request_from_app_url() {
-        let pr = PullRequestIdentifier::from_app_url(
-            &Url::parse("https://github.com/potato/smasher/pull/1337").unwrap(),
-        )
-        .unwrap();
+        let hosts = ["github.com"];
+        let pr = parse_app_url("https://github.com/potato/smasher/pull/1337", &hosts).unwrap();
         assert_eq!(pr.owner, "potato");
         assert_eq!(pr.repo, "smasher");
         assert_eq!(pr.pull_number, 1337);
 
-        assert!(PullRequestIdentifier::from_app_url(
-            &Url::parse("https://github.com/potato/smasher/pull/").unwrap()
-        )
-        .is_err());
-        assert!(PullRequestIdentifier::from_app_url(
-            &Url::parse("https://github.com//smasher/pull/").unwrap()
-        )
-        .is_err());
-        assert!(PullRequestIdentifier::from_app_url(
-            &Url::parse("https://github.com/potato/pull/1337").unwrap()
+        assert!(parse_app_url("https://github.com/potato/smasher/pull/", &hosts).is_err());
+        assert!(parse_app_url("https://github.com//smasher/pull/", &hosts).is_err());
+        assert!(parse_app_url("https://github.com/potato/pull/1337", &hosts).is_err());
+        assert!(parse_app_url("https://gitlab.com/potato/smasher/pull/1337", &hosts).is_err());
+    }
+
+    #[test]
+    fn pull_request_from_enterprise_app_url() {
+        let hosts = ["github.com", "github.mycorp.com"];
+        let pr =
+            parse_app_url("https://github.mycorp.com/potato/smasher/pull/1337", &hosts).unwrap();
+        assert_eq!(pr.pull_number, 1337);
+        assert!(parse_app_url(
+            "https://github.mycorp.com/potato/smasher/pull/1337",
+            &["github.com"]
         )
         .is_err());
     }
+
+    #[test]
+    fn timestamp_deserialization() {
+        let payload = r#"{
+            "user": {"login": "potato"},
+            "state": "APPROVED",
+            "submitted_at": "2021-03-04T10:20:30Z"
+        }"#;
+        let review: PullRequestReview = serde_json::from_str(payload).unwrap();
+        assert_eq!(
+            review.submitted_at,
+            Some(chrono::Utc.ymd(2021, 3, 4).and_hms(10, 20, 30))
+        );
+    }
+
+    #[test]
+    fn pending_review_deserialization() {
+        let payload = r#"{
+            "user": {"login": "potato"},
+            "state": "PENDING"
+        }"#;
+        let review: PullRequestReview = serde_json::from_str(payload).unwrap();
+        assert_eq!(review.state, ReviewState::Pending);
+        assert_eq!(review.submitted_at, None);
+    }
+
+    #[test]
+    fn comparison_deserialization() {
+        let payload = r#"{
+            "url": "https://api.github.com/repos/potato/smasher/compare/main...feature",
+            "status": "diverged",
+            "ahead_by": 3,
+            "behind_by": 5,
+            "total_commits": 3,
+            "base_commit": {"sha": "1111111"},
+            "merge_base_commit": {"sha": "2222222", "commit": {"message": "Base"}},
+            "commits": [],
+            "files": []
+        }"#;
+        let comparison: Comparison = serde_json::from_str(payload).unwrap();
+        assert_eq!(comparison.ahead_by, 3);
+        assert_eq!(comparison.behind_by, 5);
+        assert_eq!(comparison.merge_base_commit.sha, "2222222");
+    }
+
+    #[test]
+    fn pull_request_without_statuses_link_deserialization() {
+        let payload = r#"{
+            "mergeable_state": "clean",
+            "_links": {
+                "self": {"href": "https://api.github.com/repos/potato/smasher/pulls/1337"}
+            },
+            "user": {"login": "potato"},
+            "state": "open",
+            "title": "Smash potatoes",
+            "head": {
+                "sha": "abc123",
+                "ref": "feature",
+                "user": {"login": "potato"},
+                "repo": {"name": "smasher", "owner": {"login": "potato"}, "full_name": "potato/smasher"}
+            },
+            "base": {
+                "sha": "def456",
+                "ref": "main",
+                "user": {"login": "potato"},
+                "repo": {"name": "smasher", "owner": {"login": "potato"}, "full_name": "potato/smasher"}
+            },
+            "merged": false,
+            "draft": false,
+            "body": null,
+            "number": 1337
+        }"#;
+        let pull_request: PullRequest = serde_json::from_str(payload).unwrap();
+        assert_eq!(pull_request.links.statuses, None);
+        assert_eq!(pull_request.head.sha, "abc123");
+    }
+
+    #[test]
+    fn repository_info_deserialization() {
+        let payload = r#"{
+            "id": 1296269,
+            "name": "smasher",
+            "full_name": "potato/smasher",
+            "owner": {"login": "potato"},
+            "private": false,
+            "default_branch": "main",
+            "allow_squash_merge": true,
+            "allow_merge_commit": false,
+            "allow_rebase_merge": true,
+            "delete_branch_on_merge": true,
+            "archived": false
+        }"#;
+        let info: RepositoryInfo = serde_json::from_str(payload).unwrap();
+        assert_eq!(
+            info,
+            RepositoryInfo {
+                full_name: "potato/smasher".into(),
+                default_branch: "main".into(),
+                allow_squash_merge: true,
+                allow_merge_commit: false,
+                allow_rebase_merge: true,
+                delete_branch_on_merge: true,
+                archived: false,
+            }
+        );
+        assert!(info.allows_merge_method(&MergeMethod::Squash));
+        assert!(!info.allows_merge_method(&MergeMethod::Merge));
+
+        // Without push access the merge settings aren't returned at all
+        let info = RepositoryInfo::default();
+        assert!(info.allows_merge_method(&MergeMethod::Merge));
+    }
+
+    #[test]
+    fn combined_status_deserialization() {
+        let payload = r#"{
+            "state": "pending",
+            "sha": "abc123",
+            "total_count": 0,
+            "statuses": []
+        }"#;
+        let status: CombinedStatus = serde_json::from_str(payload).unwrap();
+        assert_eq!(
+            status,
+            CombinedStatus {
+                state: StatusState::Pending,
+                total_count: 0
+            }
+        );
+    }
+
+    #[test]
+    fn pull_request_identifier_string_round_trip() {
+        let id = PullRequestIdentifier {
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        };
+        assert_eq!(id.to_string(), "potato/smasher#1337");
+        assert_eq!(
+            "potato/smasher#1337".parse::<PullRequestIdentifier>(),
+            Ok(id)
+        );
+
+        assert!("potato/smasher".parse::<PullRequestIdentifier>().is_err());
+        assert!("potato#1337".parse::<PullRequestIdentifier>().is_err());
+        assert!("/smasher#1337".parse::<PullRequestIdentifier>().is_err());
+        assert!("potato/#1337".parse::<PullRequestIdentifier>().is_err());
+        assert!("potato/smasher/pull#1337"
+            .parse::<PullRequestIdentifier>()
+            .is_err());
+        assert!("potato/smasher#abc"
+            .parse::<PullRequestIdentifier>()
+            .is_err());
+    }
+
+    #[test]
+    fn pull_request_identifier_serde_round_trip() {
+        let id = PullRequestIdentifier {
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        };
+        let serialized = serde_json::to_string(&id).unwrap();
+        assert_eq!(serialized, r#""potato/smasher#1337""#);
+        assert_eq!(
+            serde_json::from_str::<PullRequestIdentifier>(&serialized).unwrap(),
+            id
+        );
+        assert!(serde_json::from_str::<PullRequestIdentifier>(r#""potato#1337""#).is_err());
+    }
 }
@@ -1,3 +1,4 @@
+pub mod bitbucket;
 pub mod circleci;
 pub mod client;
 pub mod common;
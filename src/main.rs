@@ -1,102 +1,318 @@
 use env_logger::Env;
-use log::{error, info};
+use futures::future::join_all;
+use log::{debug, error, info, log, warn, Level};
 use mergebro::{
+    bitbucket::{BitbucketPipelinesWorkflowRunner, DefaultBitbucketClient},
     circleci::{CircleCiWorkflowRunner, DefaultCircleCiClient},
+    client::RateLimitTracker,
     common::{RepoIdentifier, RepoMap},
-    config::{ReviewsConfig, StatusFailuresConfig},
-    github::{DefaultGithubClient, GithubClient, PullRequestIdentifier},
+    config::{
+        ActionsConfig, BehindConfig, BitbucketConfig, CommitMessagesConfig, DeploymentConfig,
+        ExternalCommandConfig, FeedbackConfig, OnErrorPolicy, ReviewsConfig, StatusFailuresConfig,
+        SweepConfig, TaskListConfig, VersionBumpConfig, WorkflowRunnerKind,
+    },
+    github::{DefaultGithubClient, DryRunGithubClient, GithubClient, PullRequestIdentifier},
     processing::{
         steps::{
-            CheckBehindMaster, CheckBuildFailed, CheckCurrentStateStep, CheckReviewsStep, Step,
+            CheckBehindMaster, CheckBuildFailed, CheckChecksRegistered, CheckCommitMessages,
+            CheckCurrentStateStep, CheckExternalCommand, CheckMergeCommand,
+            CheckNoActiveDeployment, CheckReviewsStep, CheckSensitiveBase, CheckTaskList,
+            CheckVersionBump, MarkReadyForReview, Step,
         },
-        DefaultPullRequestMerger, DummyPullRequestMerger, PullRequestMerger,
+        DefaultPullRequestMerger, DryRunWorkflowRunner, DummyPullRequestMerger, PullRequestMerger,
     },
     Director, DirectorState, MergebroConfig, WorkflowRunner,
 };
 use reqwest::Url;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::process::exit;
 use std::sync::Arc;
-use std::time::Duration;
-use structopt::StructOpt;
+use std::time::{Duration, Instant};
+use structopt::{clap::AppSettings, StructOpt};
 use tokio::time::sleep;
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "mergebro")]
+#[structopt(name = "mergebro", setting = AppSettings::SubcommandsNegateReqs)]
 struct Options {
-    /// The path to the YAML configuration file
-    #[structopt(short, long, default_value = "~/.mergebro/config.yaml")]
-    config_file: String,
+    /// The path to the YAML configuration file [default: $XDG_CONFIG_HOME/mergebro/config.yaml,
+    /// ~/.mergebro/config.yaml or ./mergebro.yaml, whichever exists first]
+    #[structopt(short, long)]
+    config_file: Option<String>,
 
     /// Whether to simply run checks but not actually merge the pull request
     #[structopt(short, long)]
     dry_run: bool,
 
+    /// Whether to perform every read only request and log every decision made, without modifying
+    /// anything (e.g. updating branches, re-running jobs, running external commands or merging)
+    #[structopt(long, conflicts_with = "dry-run")]
+    dry_run_verbose: bool,
+
     /// Whether to ignore checks for pull request reviews
     #[structopt(short = "r")]
     ignore_reviews: bool,
 
-    /// The pull request to be processed
-    #[structopt(name = "pull_request_url")]
-    pull_request_url: String,
+    /// Whether to only log state transitions and errors rather than every iteration
+    #[structopt(short, long)]
+    quiet: bool,
+
+    /// Whether to allow merging pull requests that target sensitive base branches
+    #[structopt(long)]
+    allow_sensitive_base: bool,
+
+    /// A search query that must match exactly one open pull request to be processed, e.g.
+    /// "head:my-branch repo:owner/repo"
+    #[structopt(long, conflicts_with = "pull_request_url")]
+    search: Option<String>,
+
+    /// The pull requests to be processed
+    #[structopt(name = "pull_request_url", required_unless = "search")]
+    pull_request_urls: Vec<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Periodically process every pull request that matches the configured sweep search query
+    Sweep,
 }
 
-fn parse_pull_request_url(url: &str) -> Result<PullRequestIdentifier, Box<dyn Error>> {
+fn parse_pull_request_url(
+    url: &str,
+    web_hosts: &[String],
+) -> Result<PullRequestIdentifier, Box<dyn Error>> {
     let url = Url::parse(url)?;
-    let pull_request_id = PullRequestIdentifier::from_app_url(&url)?;
+    let pull_request_id = PullRequestIdentifier::from_app_url(&url, web_hosts)?;
     Ok(pull_request_id)
 }
 
+async fn search_pull_request(
+    github: &dyn GithubClient,
+    query: &str,
+    web_hosts: &[String],
+) -> Result<PullRequestIdentifier, Box<dyn Error>> {
+    let results = github.search_pull_requests(query).await?;
+    match results.items.as_slice() {
+        [] => Err("no open pull requests match the search query".into()),
+        [item] => parse_pull_request_url(&item.html_url, web_hosts),
+        items => {
+            let urls: Vec<_> = items.iter().map(|item| item.html_url.as_str()).collect();
+            Err(format!(
+                "search query matches {} pull requests: {}",
+                results.total_count,
+                urls.join(", ")
+            )
+            .into())
+        }
+    }
+}
+
 struct SplitRepoConfigs {
     reviews_config: RepoMap<ReviewsConfig>,
+    behind_config: RepoMap<BehindConfig>,
+    actions_config: RepoMap<ActionsConfig>,
+    enabled_runners_config: RepoMap<Option<Vec<WorkflowRunnerKind>>>,
     status_failures_config: RepoMap<HashMap<String, StatusFailuresConfig>>,
+    status_runners_config: RepoMap<HashMap<String, WorkflowRunnerKind>>,
+    version_bump_config: RepoMap<Option<VersionBumpConfig>>,
+    commit_messages_config: RepoMap<Option<CommitMessagesConfig>>,
+    task_list_config: RepoMap<Option<TaskListConfig>>,
+    deployment_config: RepoMap<Option<DeploymentConfig>>,
+    external_commands_config: RepoMap<Vec<ExternalCommandConfig>>,
 }
 
 fn split_repo_configs(config: &MergebroConfig) -> Result<SplitRepoConfigs, Box<dyn Error>> {
     let mut reviews_config = RepoMap::new(config.reviews.clone());
+    let mut behind_config = RepoMap::new(config.behind.clone());
+    let mut actions_config = RepoMap::new(config.workflows.actions.clone());
+    let mut enabled_runners_config = RepoMap::default();
     let mut status_failures_config = RepoMap::default();
+    let mut status_runners_config = RepoMap::default();
+    let mut version_bump_config = RepoMap::default();
+    let mut commit_messages_config = RepoMap::default();
+    let mut task_list_config = RepoMap::default();
+    let mut deployment_config = RepoMap::default();
+    let mut external_commands_config = RepoMap::default();
     for repo_config in &config.repos {
         let repo: RepoIdentifier = repo_config.repo.parse()?;
         if let Some(reviews) = &repo_config.reviews {
             reviews_config.insert(repo.clone(), reviews.clone())?;
         }
+        if let Some(behind) = &repo_config.behind {
+            behind_config.insert(repo.clone(), behind.clone())?;
+        }
+        if let Some(actions) = repo_config
+            .workflows
+            .as_ref()
+            .and_then(|workflows| workflows.actions.as_ref())
+        {
+            actions_config.insert(repo.clone(), actions.clone())?;
+        }
+        if let Some(enabled) = repo_config
+            .workflows
+            .as_ref()
+            .and_then(|workflows| workflows.enabled.as_ref())
+        {
+            enabled_runners_config.insert(repo.clone(), Some(enabled.clone()))?;
+        }
         if !repo_config.statuses.is_empty() {
             let mut status_config = HashMap::new();
+            let mut status_runners = HashMap::new();
             for status in &repo_config.statuses {
                 // TODO: dedup
-                status_config.insert(status.name.clone(), status.failures.clone());
+                if let Some(failures) = &status.failures {
+                    status_config.insert(status.name.clone(), failures.clone());
+                }
+                if let Some(runner) = &status.runner {
+                    status_runners.insert(status.name.clone(), runner.clone());
+                }
             }
             status_failures_config.insert(repo.clone(), status_config)?;
+            status_runners_config.insert(repo.clone(), status_runners)?;
+        }
+        if let Some(version_bump) = &repo_config.version_bump {
+            version_bump_config.insert(repo.clone(), Some(version_bump.clone()))?;
+        }
+        if let Some(commit_messages) = &repo_config.commit_messages {
+            commit_messages_config.insert(repo.clone(), Some(commit_messages.clone()))?;
+        }
+        if let Some(task_list) = &repo_config.task_list {
+            task_list_config.insert(repo.clone(), Some(task_list.clone()))?;
+        }
+        if let Some(deployment) = &repo_config.deployment {
+            deployment_config.insert(repo.clone(), Some(deployment.clone()))?;
+        }
+        if !repo_config.external_commands.is_empty() {
+            external_commands_config.insert(repo.clone(), repo_config.external_commands.clone())?;
         }
     }
     Ok(SplitRepoConfigs {
         reviews_config,
+        behind_config,
+        actions_config,
+        enabled_runners_config,
         status_failures_config,
+        status_runners_config,
+        version_bump_config,
+        commit_messages_config,
+        task_list_config,
+        deployment_config,
+        external_commands_config,
     })
 }
 
 fn build_steps(
     id: &PullRequestIdentifier,
     github_client: Arc<dyn GithubClient>,
-    workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
+    workflow_runners: &BTreeMap<WorkflowRunnerKind, Arc<dyn WorkflowRunner>>,
     config: &MergebroConfig,
-    ignore_reviews: bool,
+    options: &Options,
 ) -> Result<Vec<Box<dyn Step>>, Box<dyn Error>> {
     let split_repo_configs = split_repo_configs(config)?;
-    let mut steps: Vec<Box<dyn Step>> = vec![
-        Box::new(CheckCurrentStateStep::default()),
-        Box::new(CheckBehindMaster::new(github_client.clone())),
-        Box::new(CheckBuildFailed::new(
+    let mut steps: Vec<Box<dyn Step>> = vec![Box::new(CheckCurrentStateStep::new(
+        config.current_state.clone(),
+    ))];
+    if config.command.required {
+        steps.push(Box::new(CheckMergeCommand::new(
+            github_client.clone(),
+            config.command.clone(),
+        )));
+    }
+    if !options.allow_sensitive_base && !config.merge.sensitive_base_branches.is_empty() {
+        steps.push(Box::new(CheckSensitiveBase::new(
+            &config.merge.sensitive_base_branches,
+        )?));
+    }
+    if let Some(version_bump) = split_repo_configs
+        .version_bump_config
+        .get(&id.owner, &id.repo)
+    {
+        steps.push(Box::new(CheckVersionBump::new(
+            github_client.clone(),
+            version_bump.clone(),
+        )));
+    }
+    if let Some(commit_messages) = split_repo_configs
+        .commit_messages_config
+        .get(&id.owner, &id.repo)
+    {
+        steps.push(Box::new(CheckCommitMessages::new(
             github_client.clone(),
-            workflow_runners,
+            commit_messages.clone(),
+            &config.merge.default_method,
+        )?));
+    }
+    if let Some(task_list) = split_repo_configs.task_list_config.get(&id.owner, &id.repo) {
+        steps.push(Box::new(CheckTaskList::new(task_list.clone())));
+    }
+    for command in split_repo_configs
+        .external_commands_config
+        .get(&id.owner, &id.repo)
+    {
+        steps.push(Box::new(
+            CheckExternalCommand::new(command.clone()).with_dry_run(options.dry_run_verbose),
+        ));
+    }
+    steps.push(Box::new(CheckBehindMaster::new(
+        github_client.clone(),
+        split_repo_configs
+            .behind_config
+            .get(&id.owner, &id.repo)
+            .clone(),
+    )));
+    steps.push(Box::new(CheckChecksRegistered::new(github_client.clone())));
+    let mut status_runners = HashMap::new();
+    for (status, kind) in split_repo_configs
+        .status_runners_config
+        .get(&id.owner, &id.repo)
+    {
+        let runner = workflow_runners.get(kind).ok_or_else(|| {
+            format!(
+                "status check '{}' uses runner {:?} which is not configured",
+                status, kind
+            )
+        })?;
+        status_runners.insert(status.clone(), runner.clone());
+    }
+    let enabled_runners = match split_repo_configs
+        .enabled_runners_config
+        .get(&id.owner, &id.repo)
+    {
+        Some(kinds) => {
+            let mut runners = Vec::new();
+            for kind in kinds {
+                let runner = workflow_runners.get(kind).ok_or_else(|| {
+                    format!("workflow runner {:?} is enabled but not configured", kind)
+                })?;
+                runners.push(runner.clone());
+            }
+            runners
+        }
+        // Ordered by kind so every pull request tries runners in the same order
+        None => workflow_runners.values().cloned().collect(),
+    };
+    steps.push(Box::new(
+        CheckBuildFailed::new(
+            github_client.clone(),
+            enabled_runners,
             split_repo_configs
                 .status_failures_config
                 .get(&id.owner, &id.repo)
                 .clone(),
-        )?),
-    ];
-    if !ignore_reviews {
+            config.statuses.clone(),
+        )?
+        .with_status_runners(status_runners)
+        .with_ignored_actions(
+            &split_repo_configs
+                .actions_config
+                .get(&id.owner, &id.repo)
+                .ignore,
+        )?,
+    ));
+    if !options.ignore_reviews {
         steps.push(Box::new(CheckReviewsStep::new(
             github_client.clone(),
             split_repo_configs
@@ -105,88 +321,457 @@ fn build_steps(
                 .clone(),
         )?));
     }
+    if let Some(deployment) = split_repo_configs
+        .deployment_config
+        .get(&id.owner, &id.repo)
+    {
+        steps.push(Box::new(CheckNoActiveDeployment::new(
+            github_client.clone(),
+            deployment.clone(),
+        )));
+    }
+    if config.current_state.mark_ready_when_passed {
+        steps.push(Box::new(MarkReadyForReview::new(github_client.clone())));
+    }
     Ok(steps)
 }
 
+/// Identifies the timeout comment so later timeouts update it rather than adding another one
+const TIMEOUT_COMMENT_MARKER: &str = "<!-- mergebro:timeout -->";
+
+async fn report_timeout(
+    github: &dyn GithubClient,
+    identifier: &PullRequestIdentifier,
+    blocking_step: &str,
+    elapsed: Duration,
+    config: &FeedbackConfig,
+) -> Result<(), Box<dyn Error>> {
+    if !config.comment_on_timeout && config.timeout_label.is_none() {
+        return Ok(());
+    }
+    let pull_request = github.pull_request_info(identifier).await?;
+    if config.comment_on_timeout {
+        let comment = format!(
+            "{}\nmergebro gave up after {} minutes waiting on: {}",
+            TIMEOUT_COMMENT_MARKER,
+            elapsed.as_secs() / 60,
+            blocking_step
+        );
+        let existing_comment = github
+            .list_comments(&pull_request)
+            .await?
+            .into_iter()
+            .find(|comment| comment.body.contains(TIMEOUT_COMMENT_MARKER));
+        match existing_comment {
+            Some(existing_comment) => {
+                github
+                    .update_comment(&pull_request, existing_comment.id, &comment)
+                    .await?;
+            }
+            None => {
+                github.create_comment(&pull_request, &comment).await?;
+            }
+        }
+    }
+    if let Some(label) = &config.timeout_label {
+        github.add_label(&pull_request, label).await?;
+    }
+    Ok(())
+}
+
+enum PullRequestOutcome {
+    Merged(Option<String>),
+    TimedOut(String),
+    Failed(String),
+}
+
+struct PullRequestJob {
+    identifier: PullRequestIdentifier,
+    director: Director,
+    started: Instant,
+    outcome: Option<PullRequestOutcome>,
+}
+
+impl PullRequestJob {
+    /// Runs a single iteration of checks, setting the outcome once the pull request is done
+    async fn run(
+        &mut self,
+        github: &dyn GithubClient,
+        config: &MergebroConfig,
+        routine_level: Level,
+    ) {
+        log!(
+            routine_level,
+            "Running checks on pull request {}...",
+            self.identifier
+        );
+        let max_duration = config.poll.max_duration_seconds.map(Duration::from_secs);
+        match self.director.run().await {
+            Ok(DirectorState::Waiting)
+                if max_duration.is_some_and(|max| self.started.elapsed() >= max) =>
+            {
+                let blocking_step = self.director.blocking_step().unwrap_or("unknown step");
+                let elapsed = self.started.elapsed();
+                error!(
+                    "Giving up on {} after {} seconds waiting on: {}",
+                    self.identifier,
+                    elapsed.as_secs(),
+                    blocking_step
+                );
+                let result = report_timeout(
+                    github,
+                    &self.identifier,
+                    blocking_step,
+                    elapsed,
+                    &config.feedback,
+                )
+                .await;
+                if let Err(e) = result {
+                    error!("Failed to report timeout on pull request: {}", e);
+                }
+                self.outcome = Some(PullRequestOutcome::TimedOut(blocking_step.into()));
+            }
+            Ok(DirectorState::Waiting) => (),
+            Ok(DirectorState::Done) => {
+                let merge_sha = self.director.merge_sha().map(String::from);
+                self.outcome = Some(PullRequestOutcome::Merged(merge_sha));
+            }
+            Err(e) => {
+                error!("Error processing pull request {}: {}", self.identifier, e);
+                self.outcome = Some(PullRequestOutcome::Failed(e.to_string()));
+            }
+        }
+    }
+}
+
+/// The level used to log messages emitted on every iteration
+fn routine_level(options: &Options) -> Level {
+    if options.quiet {
+        Level::Debug
+    } else {
+        Level::Info
+    }
+}
+
+/// Runs an iteration on every job that's still in progress, all of them concurrently
+async fn run_iteration(
+    jobs: &mut [PullRequestJob],
+    github: &dyn GithubClient,
+    config: &MergebroConfig,
+    options: &Options,
+) {
+    let routine_level = routine_level(options);
+    let pending = jobs
+        .iter_mut()
+        .filter(|job| job.outcome.is_none())
+        .map(|job| job.run(github, config, routine_level));
+    join_all(pending).await;
+}
+
+/// Waits longer than usual between iterations when close to hitting the rate limit
+fn throttled_delay(delay: Duration, rate_limit: &RateLimitTracker) -> Duration {
+    match rate_limit.throttle_delay() {
+        Some(throttle) if throttle > delay => {
+            warn!(
+                "Close to hitting the GitHub rate limit, pausing for {} seconds until it resets",
+                throttle.as_secs()
+            );
+            throttle
+        }
+        _ => delay,
+    }
+}
+
+async fn run_jobs(
+    jobs: &mut [PullRequestJob],
+    github: &dyn GithubClient,
+    rate_limit: &RateLimitTracker,
+    config: &MergebroConfig,
+    options: &Options,
+) {
+    let sleep_duration = Duration::from_secs(config.poll.delay_seconds as u64);
+    loop {
+        run_iteration(jobs, github, config, options).await;
+        let any_failed = jobs
+            .iter()
+            .any(|job| matches!(job.outcome, Some(PullRequestOutcome::Failed(_))));
+        if any_failed && config.multi.on_error == OnErrorPolicy::Stop {
+            return;
+        }
+        if jobs.iter().all(|job| job.outcome.is_some()) {
+            return;
+        }
+        let sleep_duration = throttled_delay(sleep_duration, rate_limit);
+        log!(
+            routine_level(options),
+            "Sleeping for {} seconds",
+            sleep_duration.as_secs()
+        );
+        sleep(sleep_duration).await;
+    }
+}
+
+/// Creates the jobs that process pull requests
+struct JobFactory<'a> {
+    github_client: Arc<dyn GithubClient>,
+    workflow_runners: &'a BTreeMap<WorkflowRunnerKind, Arc<dyn WorkflowRunner>>,
+    merger: Arc<dyn PullRequestMerger>,
+    config: &'a MergebroConfig,
+    options: &'a Options,
+}
+
+impl JobFactory<'_> {
+    fn make_job(
+        &self,
+        identifier: PullRequestIdentifier,
+    ) -> Result<PullRequestJob, Box<dyn Error>> {
+        info!(
+            "Starting loop on pull request: {} using github user {}",
+            identifier, self.config.github.username
+        );
+        let steps = build_steps(
+            &identifier,
+            self.github_client.clone(),
+            self.workflow_runners,
+            self.config,
+            self.options,
+        )?;
+        let director = Director::new(
+            self.github_client.clone(),
+            self.merger.clone(),
+            steps,
+            identifier.clone(),
+        )
+        .with_quiet(self.options.quiet)
+        .with_disable_native_on_block(self.config.merge.disable_native_on_block)
+        .with_own_status(self.config.feedback.own_status);
+        Ok(PullRequestJob {
+            identifier,
+            director,
+            started: Instant::now(),
+            outcome: None,
+        })
+    }
+}
+
+async fn sweep_pull_requests(
+    github: &dyn GithubClient,
+    query: &str,
+    web_hosts: &[String],
+) -> Result<Vec<PullRequestIdentifier>, Box<dyn Error>> {
+    let results = github.search_pull_requests(query).await?;
+    let mut identifiers = Vec::new();
+    for item in results.items {
+        match parse_pull_request_url(&item.html_url, web_hosts) {
+            Ok(identifier) => identifiers.push(identifier),
+            Err(e) => error!("Ignoring pull request '{}': {}", item.html_url, e),
+        }
+    }
+    Ok(identifiers)
+}
+
+/// Runs the sweep search periodically, processing every pull request it matches until it's
+/// either merged, fails, times out, or stops matching. Pull requests that fail or time out are
+/// skipped until they stop matching the search.
+async fn run_sweep(
+    factory: &JobFactory<'_>,
+    rate_limit: &RateLimitTracker,
+    config: &SweepConfig,
+) -> Result<(), Box<dyn Error>> {
+    let query = config.query.as_ref().ok_or("no sweep query configured")?;
+    let github = &*factory.github_client;
+    let interval = Duration::from_secs(config.interval_seconds);
+    let mut jobs: Vec<PullRequestJob> = Vec::new();
+    let mut finished: HashSet<PullRequestIdentifier> = HashSet::new();
+    loop {
+        debug!("Running sweep search: {}", query);
+        match sweep_pull_requests(github, query, &factory.config.github.hosts).await {
+            Ok(identifiers) => {
+                finished.retain(|identifier| identifiers.contains(identifier));
+                jobs.retain(|job| identifiers.contains(&job.identifier));
+                for identifier in identifiers {
+                    if finished.contains(&identifier)
+                        || jobs.iter().any(|job| job.identifier == identifier)
+                    {
+                        continue;
+                    }
+                    if config.max_concurrent.is_some_and(|max| jobs.len() >= max) {
+                        debug!(
+                            "Too many pull requests in progress, skipping {}",
+                            identifier
+                        );
+                        continue;
+                    }
+                    match factory.make_job(identifier.clone()) {
+                        Ok(job) => jobs.push(job),
+                        Err(e) => {
+                            error!("Failed to initialize step checks: {}", e);
+                            finished.insert(identifier);
+                        }
+                    }
+                }
+            }
+            Err(e) => error!("Error running sweep search: {}", e),
+        };
+        run_iteration(&mut jobs, github, factory.config, factory.options).await;
+        for job in jobs.iter().filter(|job| job.outcome.is_some()) {
+            if let Some(PullRequestOutcome::Merged(_)) = job.outcome {
+                info!("Pull request {} was merged", job.identifier);
+            }
+            finished.insert(job.identifier.clone());
+        }
+        jobs.retain(|job| job.outcome.is_none());
+        let interval = throttled_delay(interval, rate_limit);
+        debug!(
+            "Sweep done, {} pull requests in progress, sleeping for {} seconds",
+            jobs.len(),
+            interval.as_secs()
+        );
+        sleep(interval).await;
+    }
+}
+
+fn log_summary(jobs: &[PullRequestJob]) {
+    info!("Summary:");
+    for job in jobs {
+        match &job.outcome {
+            Some(PullRequestOutcome::Merged(Some(sha))) => {
+                info!("  {}: merged as {}", job.identifier, sha)
+            }
+            Some(PullRequestOutcome::Merged(None)) => info!("  {}: merged", job.identifier),
+            Some(PullRequestOutcome::TimedOut(step)) => {
+                info!("  {}: gave up waiting on {}", job.identifier, step)
+            }
+            Some(PullRequestOutcome::Failed(e)) => info!("  {}: failed: {}", job.identifier, e),
+            None => info!("  {}: still waiting", job.identifier),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
-
     let options = Options::from_args();
-    let config = match MergebroConfig::new(&options.config_file) {
+    let default_filter = if options.dry_run_verbose {
+        "info,mergebro=debug"
+    } else {
+        "info"
+    };
+    env_logger::Builder::from_env(Env::default().default_filter_or(default_filter)).init();
+
+    let config = match MergebroConfig::new(options.config_file.as_deref()) {
         Ok(config) => config,
         Err(e) => {
-            error!("Error parsing config: {}", e);
+            error!("Error loading config: {}", e);
             exit(1);
         }
     };
 
-    let github_client = Arc::new(DefaultGithubClient::new(
+    let default_github_client = DefaultGithubClient::new(
         &config.github.username,
         config.github.token.clone(),
-    ));
-    let identifier = match parse_pull_request_url(&options.pull_request_url) {
-        Ok(identifier) => identifier,
-        Err(e) => {
-            error!("Error parsing pull request URL: {}", e);
-            exit(1);
-        }
-    };
+        &config.http,
+    )
+    .with_api_base(&config.github.endpoint);
+    let rate_limit = default_github_client.rate_limit();
+    let mut github_client: Arc<dyn GithubClient> = Arc::new(default_github_client);
+    if options.dry_run_verbose {
+        github_client = Arc::new(DryRunGithubClient::new(github_client));
+    }
+    let mut identifiers = Vec::new();
+    if let Some(query) = &options.search {
+        match search_pull_request(&*github_client, query, &config.github.hosts).await {
+            Ok(identifier) => identifiers.push(identifier),
+            Err(e) => {
+                error!("Error searching for pull request: {}", e);
+                exit(1);
+            }
+        };
+    }
+    for url in &options.pull_request_urls {
+        match parse_pull_request_url(url, &config.github.hosts) {
+            Ok(identifier) => identifiers.push(identifier),
+            Err(e) => {
+                error!("Error parsing pull request URL '{}': {}", url, e);
+                exit(1);
+            }
+        };
+    }
 
-    let mut workflow_runners: Vec<Arc<dyn WorkflowRunner>> = Vec::new();
-    if config.workflows.circleci.is_some() {
-        let token = config.workflows.circleci.as_ref().unwrap().token.clone();
-        let circleci_client = Arc::new(DefaultCircleCiClient::new(token));
-        workflow_runners.push(Arc::new(CircleCiWorkflowRunner::new(circleci_client)));
+    let mut workflow_runners: BTreeMap<WorkflowRunnerKind, Arc<dyn WorkflowRunner>> =
+        BTreeMap::new();
+    if let Some(circleci_config) = &config.workflows.circleci {
+        let token = circleci_config.token.clone();
+        let circleci_client = Arc::new(DefaultCircleCiClient::new(token, &config.http));
+        workflow_runners.insert(
+            WorkflowRunnerKind::CircleCi,
+            Arc::new(CircleCiWorkflowRunner::new(
+                circleci_client,
+                circleci_config.rerun.clone(),
+            )),
+        );
+    }
+    if let Some(bitbucket_config) = &config.workflows.bitbucket {
+        let bitbucket_client = Arc::new(match bitbucket_config {
+            BitbucketConfig::AppPassword { username, password } => {
+                DefaultBitbucketClient::new(username, password, &config.http)
+            }
+            BitbucketConfig::AccessToken { access_token } => {
+                DefaultBitbucketClient::from_access_token(access_token, &config.http)
+            }
+        });
+        workflow_runners.insert(
+            WorkflowRunnerKind::Bitbucket,
+            Arc::new(BitbucketPipelinesWorkflowRunner::new(bitbucket_client)),
+        );
     }
 
+    if options.dry_run_verbose {
+        for runner in workflow_runners.values_mut() {
+            *runner = Arc::new(DryRunWorkflowRunner::new(runner.clone()));
+        }
+    }
     if workflow_runners.is_empty() {
         info!("No external workflow runners configured");
     } else {
         info!("Using {} external workflow runners", workflow_runners.len());
     }
 
-    let merger: Arc<dyn PullRequestMerger> = if options.dry_run {
+    let merger: Arc<dyn PullRequestMerger> = if options.dry_run_verbose {
+        info!("Running in verbose dry-run mode, nothing will be modified");
+        Arc::new(DummyPullRequestMerger)
+    } else if options.dry_run {
         info!("Running in dry-run mode");
-        Arc::new(DummyPullRequestMerger::default())
+        Arc::new(DummyPullRequestMerger)
     } else {
         Arc::new(DefaultPullRequestMerger::new(config.merge.clone()))
     };
 
-    let sleep_duration = Duration::from_secs(config.poll.delay_seconds as u64);
-    info!(
-        "Starting loop on pull request: {}/{}/pulls/{} using github user {}",
-        identifier.owner, identifier.repo, identifier.pull_number, config.github.username
-    );
-    let steps = build_steps(
-        &identifier,
-        github_client.clone(),
-        workflow_runners,
-        &config,
-        options.ignore_reviews,
-    );
-    let steps = match steps {
-        Ok(steps) => steps,
-        Err(e) => {
-            error!("Failed to initialize step checks: {}", e);
+    let factory = JobFactory {
+        github_client: github_client.clone(),
+        workflow_runners: &workflow_runners,
+        merger,
+        config: &config,
+        options: &options,
+    };
+    if let Some(Command::Sweep) = options.command {
+        if let Err(e) = run_sweep(&factory, &rate_limit, &config.sweep).await {
+            error!("Error running sweep: {}", e);
             exit(1);
         }
-    };
-    let mut director = Director::new(github_client, merger, steps, identifier);
-    loop {
-        info!("Running checks on pull request...");
-        match director.run().await {
-            Ok(DirectorState::Waiting) => {
-                info!("Sleeping for {} seconds", sleep_duration.as_secs());
-                sleep(sleep_duration).await;
-            }
-            Ok(DirectorState::Done) => {
-                break;
-            }
+        return;
+    }
+    let mut jobs = Vec::new();
+    for identifier in identifiers {
+        match factory.make_job(identifier) {
+            Ok(job) => jobs.push(job),
             Err(e) => {
-                error!("Error processing pull request: {}", e);
-                break;
+                error!("Failed to initialize step checks: {}", e);
+                exit(1);
             }
-        }
+        };
+    }
+    run_jobs(&mut jobs, &*github_client, &rate_limit, &config, &options).await;
+    if jobs.len() > 1 {
+        log_summary(&jobs);
     }
 }
@@ -1,7 +1,9 @@
 use super::steps::{Step, StepStatus};
 use super::{merge::MergeResult, Error, PullRequestMerger};
-use crate::github::{GithubClient, PullRequestIdentifier};
-use log::{debug, info};
+use crate::github::{
+    GithubClient, MergeableState, PullRequest, PullRequestIdentifier, PullRequestState, StatusState,
+};
+use log::{debug, info, log, warn, Level};
 use std::sync::Arc;
 
 pub struct Director {
@@ -9,9 +11,22 @@ pub struct Director {
     identifier: PullRequestIdentifier,
     steps: Vec<Box<dyn Step>>,
     merger: Arc<dyn PullRequestMerger>,
+    blocking_step: Option<String>,
+    last_head_sha: Option<String>,
+    merge_sha: Option<String>,
+    branch_updated: bool,
+    quiet: bool,
+    disable_native_on_block: bool,
+    own_status: bool,
+    // The last commit status reported, to avoid reporting the same one on every run
+    last_status: Option<(String, StatusState, String)>,
 }
 
 impl Director {
+    const STATUS_CONTEXT: &'static str = "mergebro";
+    // GitHub rejects longer commit status descriptions
+    const MAX_STATUS_DESCRIPTION_LENGTH: usize = 140;
+
     pub fn new(
         github: Arc<dyn GithubClient>,
         merger: Arc<dyn PullRequestMerger>,
@@ -23,31 +38,229 @@ impl Director {
             identifier,
             steps,
             merger,
+            blocking_step: None,
+            last_head_sha: None,
+            merge_sha: None,
+            branch_updated: false,
+            quiet: false,
+            disable_native_on_block: false,
+            own_status: false,
+            last_status: None,
         }
     }
 
+    /// In quiet mode, routine messages are only logged when the state changes between runs
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Whether to disable GitHub's native auto-merge when processing the pull request is aborted
+    pub fn with_disable_native_on_block(mut self, disable_native_on_block: bool) -> Self {
+        self.disable_native_on_block = disable_native_on_block;
+        self
+    }
+
+    /// Whether to report progress as a commit status on the pull request's head
+    pub fn with_own_status(mut self, own_status: bool) -> Self {
+        self.own_status = own_status;
+        self
+    }
+
+    /// The name of the step the last run was waiting on, if any
+    pub fn blocking_step(&self) -> Option<&str> {
+        self.blocking_step.as_deref()
+    }
+
+    /// The SHA of the merge commit, once the pull request was merged
+    pub fn merge_sha(&self) -> Option<&str> {
+        self.merge_sha.as_deref()
+    }
+
     pub async fn run(&mut self) -> Result<DirectorState, Error> {
         debug!("Fetching current state for pull request");
         let pull_request = self.github.pull_request_info(&self.identifier).await?;
+        debug!(
+            "Pull request is {:?} with mergeable state {:?}",
+            pull_request.state, pull_request.mergeable_state
+        );
+        if self.last_head_sha.as_ref() != Some(&pull_request.head.sha) {
+            if self.last_head_sha.is_some() {
+                info!("Pull request head changed to {}", pull_request.head.sha);
+            }
+            self.last_head_sha = Some(pull_request.head.sha.clone());
+        }
+        match self.process(&pull_request).await {
+            Err(e) => {
+                self.disable_native_auto_merge(&pull_request).await;
+                self.report_status(&pull_request, StatusState::Failure, e.to_string())
+                    .await;
+                Err(e)
+            }
+            state => state,
+        }
+    }
+
+    /// Runs the steps and merges the pull request if they all pass. Any error returned aborts
+    /// processing it.
+    async fn process(&mut self, pull_request: &PullRequest) -> Result<DirectorState, Error> {
+        // Right after updating the branch GitHub may briefly report it as conflicting while it
+        // recomputes its state, so give it one more iteration before trusting it
+        if std::mem::take(&mut self.branch_updated)
+            && matches!(
+                pull_request.mergeable_state,
+                MergeableState::Dirty | MergeableState::Unknown
+            )
+        {
+            info!("Branch was just updated, waiting for GitHub to check whether it can be merged");
+            self.blocking_step = Some("mergeability check".into());
+            self.report_waiting(pull_request).await;
+            return Ok(DirectorState::Waiting);
+        }
+        let previous_blocking_step = self.blocking_step.take();
         for step in &mut self.steps {
-            let step_status = step.execute(&pull_request).await?;
+            let step_name = step.to_string();
+            let level = if self.quiet && previous_blocking_step.as_ref() == Some(&step_name) {
+                Level::Debug
+            } else {
+                Level::Info
+            };
+            let step_status = match step.execute(pull_request).await {
+                Err(Error::PolicyBlock {
+                    terminal: false,
+                    reason,
+                }) => {
+                    log!(level, "Step '{}' is blocked: {}", step_name, reason);
+                    StepStatus::Waiting
+                }
+                other => other?,
+            };
             match step_status {
                 StepStatus::Waiting => {
-                    info!("Step '{}' is pending", step);
+                    log!(level, "Step '{}' is pending", step_name);
+                    self.blocking_step = Some(step_name);
+                    self.report_waiting(pull_request).await;
+                    return Ok(DirectorState::Waiting);
+                }
+                StepStatus::BranchUpdated => {
+                    log!(level, "Step '{}' updated the branch", step_name);
+                    self.blocking_step = Some(step_name);
+                    self.branch_updated = true;
+                    self.report_waiting(pull_request).await;
                     return Ok(DirectorState::Waiting);
                 }
                 StepStatus::Passed => debug!("Step '{}' passed", step),
             };
         }
         info!("All checks passed, pull request is ready to be merged!");
+        let pull_request = match self.refetch_before_merge(pull_request).await? {
+            Some(pull_request) => pull_request,
+            None => {
+                self.blocking_step = Some("merge".into());
+                return Ok(DirectorState::Waiting);
+            }
+        };
+        self.report_status(
+            &pull_request,
+            StatusState::Success,
+            "All checks passed".into(),
+        )
+        .await;
         match self.merger.merge(&pull_request, &*self.github).await? {
-            MergeResult::Success => Ok(DirectorState::Done),
+            MergeResult::Success { merge_sha } => {
+                self.merge_sha = merge_sha;
+                Ok(DirectorState::Done)
+            }
             MergeResult::Conflict => {
                 info!("Found conflict while attempting merge");
+                self.blocking_step = Some("merge".into());
+                Ok(DirectorState::Waiting)
+            }
+            MergeResult::Deferred => {
+                self.blocking_step = Some("native auto-merge".into());
                 Ok(DirectorState::Waiting)
             }
         }
     }
+
+    async fn report_waiting(&mut self, pull_request: &PullRequest) {
+        let blocking_step = self.blocking_step.as_deref().unwrap_or("unknown step");
+        let description = format!("Waiting on: {}", blocking_step);
+        self.report_status(pull_request, StatusState::Pending, description)
+            .await;
+    }
+
+    async fn report_status(
+        &mut self,
+        pull_request: &PullRequest,
+        state: StatusState,
+        mut description: String,
+    ) {
+        if !self.own_status {
+            return;
+        }
+        if description.len() > Self::MAX_STATUS_DESCRIPTION_LENGTH {
+            let mut length = Self::MAX_STATUS_DESCRIPTION_LENGTH - 3;
+            while !description.is_char_boundary(length) {
+                length -= 1;
+            }
+            description.truncate(length);
+            description.push_str("...");
+        }
+        let status = (pull_request.head.sha.clone(), state, description);
+        if self.last_status.as_ref() == Some(&status) {
+            return;
+        }
+        let (_, state, description) = &status;
+        let result = self
+            .github
+            .create_commit_status(
+                pull_request,
+                Self::STATUS_CONTEXT,
+                state.clone(),
+                description,
+            )
+            .await;
+        match result {
+            Ok(_) => self.last_status = Some(status),
+            Err(e) => warn!("Failed to report commit status: {}", e),
+        }
+    }
+
+    async fn disable_native_auto_merge(&self, pull_request: &PullRequest) {
+        if !self.disable_native_on_block || pull_request.auto_merge.is_none() {
+            return;
+        }
+        info!("Disabling native auto-merge as the pull request can't be merged");
+        if let Err(e) = self.github.disable_auto_merge(pull_request).await {
+            warn!("Failed to disable native auto-merge: {}", e);
+        }
+    }
+
+    /// Fetches the pull request again to make sure nothing changed while the steps were running.
+    /// Returns `None` if we should wait until the next iteration before merging.
+    async fn refetch_before_merge(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<Option<PullRequest>, Error> {
+        let latest = self.github.pull_request_info(&self.identifier).await?;
+        match latest.state {
+            PullRequestState::Open => (),
+            PullRequestState::Closed if latest.merged => {
+                return Err(Error::terminal_block("pull request was merged externally"))
+            }
+            _ => return Err(Error::terminal_block("pull request was closed")),
+        };
+        if latest.draft {
+            info!("Pull request was converted into a draft, not merging it");
+            Ok(None)
+        } else if latest.head.sha != pull_request.head.sha {
+            info!("Pull request head changed while running checks, not merging it");
+            Ok(None)
+        } else {
+            Ok(Some(latest))
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -55,3 +268,321 @@ pub enum DirectorState {
     Done,
     Waiting,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BehindConfig, HttpConfig};
+    use crate::github::client::MockGithubClient;
+    use crate::github::{AutoMerge, DefaultGithubClient, NoBody};
+    use crate::processing::steps::{CheckBehindMaster, CheckCurrentStateStep};
+    use crate::processing::DummyPullRequestMerger;
+    use async_trait::async_trait;
+    use rstest::rstest;
+    use std::fmt;
+    use std::future;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    enum TestStep {
+        Passed,
+        BranchUpdated,
+        TransientBlock,
+        TerminalBlock,
+        Broken,
+    }
+
+    #[async_trait]
+    impl Step for TestStep {
+        async fn execute(&mut self, _pull_request: &PullRequest) -> Result<StepStatus, Error> {
+            match self {
+                Self::Passed => Ok(StepStatus::Passed),
+                Self::BranchUpdated => Ok(StepStatus::BranchUpdated),
+                Self::TransientBlock => Err(Error::transient_block("not yet")),
+                Self::TerminalBlock => Err(Error::terminal_block("nope")),
+                Self::Broken => Err(Error::as_generic("broken")),
+            }
+        }
+    }
+
+    impl fmt::Display for TestStep {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "test step")
+        }
+    }
+
+    fn make_open_pull_request() -> PullRequest {
+        PullRequest {
+            state: PullRequestState::Open,
+            ..Default::default()
+        }
+    }
+
+    fn make_identifier() -> PullRequestIdentifier {
+        PullRequestIdentifier {
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        }
+    }
+
+    fn make_director(pull_requests: Vec<PullRequest>, step: TestStep) -> Director {
+        let mut github = MockGithubClient::default();
+        for pull_request in pull_requests {
+            github
+                .expect_pull_request_info()
+                .times(1)
+                .returning(move |_| Box::pin(future::ready(Ok(pull_request.clone()))));
+        }
+        Director::new(
+            Arc::new(github),
+            Arc::new(DummyPullRequestMerger),
+            vec![Box::new(step)],
+            make_identifier(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_transient_policy_block_waits() {
+        let mut director = make_director(vec![make_open_pull_request()], TestStep::TransientBlock);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert_eq!(director.blocking_step(), Some("test step"));
+    }
+
+    #[tokio::test]
+    async fn test_terminal_policy_block_aborts() {
+        let mut director = make_director(vec![make_open_pull_request()], TestStep::TerminalBlock);
+        assert!(director.run().await.is_err());
+    }
+
+    #[rstest]
+    #[case(TestStep::TerminalBlock, true, 1)]
+    #[case(TestStep::Broken, true, 1)]
+    #[case(TestStep::TerminalBlock, false, 0)]
+    #[tokio::test]
+    async fn test_abort_disables_native_auto_merge(
+        #[case] step: TestStep,
+        #[case] disable_native_on_block: bool,
+        #[case] expected_calls: usize,
+    ) {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_info().returning(|_| {
+            let pull_request = PullRequest {
+                auto_merge: Some(AutoMerge::default()),
+                ..make_open_pull_request()
+            };
+            Box::pin(future::ready(Ok(pull_request)))
+        });
+        github
+            .expect_disable_auto_merge()
+            .times(expected_calls)
+            .returning(|_| Box::pin(future::ready(Ok(()))));
+        let mut director = Director::new(
+            Arc::new(github),
+            Arc::new(DummyPullRequestMerger),
+            vec![Box::new(step)],
+            make_identifier(),
+        )
+        .with_disable_native_on_block(disable_native_on_block);
+        assert!(director.run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_conflict_after_branch_update_waits_once() {
+        let dirty = PullRequest {
+            mergeable_state: MergeableState::Dirty,
+            ..make_open_pull_request()
+        };
+        let mut director = make_director(
+            vec![make_open_pull_request(), dirty.clone(), dirty],
+            TestStep::BranchUpdated,
+        );
+        director
+            .steps
+            .insert(0, Box::new(CheckCurrentStateStep::default()));
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert_eq!(director.blocking_step(), Some("mergeability check"));
+        assert!(director.run().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_own_status_reported_once_while_waiting() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .times(2)
+            .returning(|_| Box::pin(future::ready(Ok(make_open_pull_request()))));
+        github
+            .expect_create_commit_status()
+            .withf(|_, context, state, description| {
+                context == "mergebro"
+                    && *state == StatusState::Pending
+                    && description == "Waiting on: test step"
+            })
+            .times(1)
+            .returning(|_, _, _, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let mut director = Director::new(
+            Arc::new(github),
+            Arc::new(DummyPullRequestMerger),
+            vec![Box::new(TestStep::TransientBlock)],
+            make_identifier(),
+        )
+        .with_own_status(true);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+    }
+
+    #[rstest]
+    #[case(TestStep::TerminalBlock, StatusState::Failure)]
+    #[case(TestStep::Broken, StatusState::Failure)]
+    #[case(TestStep::Passed, StatusState::Success)]
+    #[tokio::test]
+    async fn test_own_status_reports_outcome(
+        #[case] step: TestStep,
+        #[case] expected: StatusState,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_info()
+            .returning(|_| Box::pin(future::ready(Ok(make_open_pull_request()))));
+        github
+            .expect_create_commit_status()
+            .withf(move |_, _, state, _| *state == expected)
+            .times(1)
+            .returning(|_, _, _, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let mut director = Director::new(
+            Arc::new(github),
+            Arc::new(DummyPullRequestMerger),
+            vec![Box::new(step)],
+            make_identifier(),
+        )
+        .with_own_status(true);
+        let _ = director.run().await;
+    }
+
+    #[tokio::test]
+    async fn test_merge() {
+        let pull_requests = vec![make_open_pull_request(), make_open_pull_request()];
+        let mut director = make_director(pull_requests, TestStep::Passed);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Done);
+    }
+
+    #[tokio::test]
+    async fn test_draft_before_merge_waits() {
+        let draft = PullRequest {
+            draft: true,
+            ..make_open_pull_request()
+        };
+        let mut director = make_director(vec![make_open_pull_request(), draft], TestStep::Passed);
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+    }
+
+    /// Builds a minimal open pull request payload as returned by the GitHub API
+    fn pull_request_payload(
+        identifier: &PullRequestIdentifier,
+        mergeable_state: &str,
+    ) -> serde_json::Value {
+        let repo = serde_json::json!({
+            "name": identifier.repo,
+            "owner": {"login": identifier.owner},
+            "full_name": format!("{}/{}", identifier.owner, identifier.repo),
+        });
+        serde_json::json!({
+            "mergeable_state": mergeable_state,
+            "user": {"login": "potato"},
+            "state": "open",
+            "title": "Smash potatoes",
+            "head": {
+                "sha": "abc123",
+                "ref": "feature",
+                "user": {"login": identifier.owner},
+                "repo": repo,
+            },
+            "base": {
+                "sha": "def456",
+                "ref": "main",
+                "user": {"login": identifier.owner},
+                "repo": repo,
+            },
+            "merged": false,
+            "draft": false,
+            "body": null,
+            "number": identifier.pull_number,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_behind_pull_request_updates_branch() {
+        let server = MockServer::start().await;
+        let identifier = PullRequestIdentifier {
+            owner: "potato".into(),
+            repo: "smasher".into(),
+            pull_number: 1337,
+        };
+        Mock::given(method("GET"))
+            .and(path("/repos/potato/smasher/pulls/1337"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(pull_request_payload(&identifier, "behind")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/repos/potato/smasher/branches/main/protection"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "required_linear_history": {"enabled": false},
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("PUT"))
+            .and(path("/repos/potato/smasher/pulls/1337/update-branch"))
+            .respond_with(
+                ResponseTemplate::new(202)
+                    .set_body_json(serde_json::json!({"message": "Updating pull request branch."})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        let github = Arc::new(
+            DefaultGithubClient::new("mergebro", "token", &HttpConfig::default())
+                .with_api_base(server.uri()),
+        );
+        let step = CheckBehindMaster::new(github.clone(), BehindConfig::default());
+        let mut director = Director::new(
+            github,
+            Arc::new(DummyPullRequestMerger),
+            vec![Box::new(step)],
+            identifier,
+        );
+        assert_eq!(director.run().await.unwrap(), DirectorState::Waiting);
+
+        let requests: Vec<_> = server
+            .received_requests()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|request| format!("{} {}", request.method, request.url.path()))
+            .collect();
+        assert_eq!(
+            requests,
+            vec![
+                "GET /repos/potato/smasher/pulls/1337",
+                "GET /repos/potato/smasher/branches/main/protection",
+                "PUT /repos/potato/smasher/pulls/1337/update-branch"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_before_merge_aborts() {
+        let closed = PullRequest {
+            state: PullRequestState::Closed,
+            ..Default::default()
+        };
+        let mut director = make_director(vec![make_open_pull_request(), closed], TestStep::Passed);
+        assert!(director.run().await.is_err());
+    }
+}
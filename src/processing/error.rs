@@ -9,6 +9,12 @@ pub enum Error {
     #[error("unsupported pull request state: {0}")]
     UnsupportedPullRequestState(Cow<'static, str>),
 
+    #[error("{reason}")]
+    PolicyBlock {
+        terminal: bool,
+        reason: Cow<'static, str>,
+    },
+
     #[error("{0}")]
     Generic(Cow<'static, str>),
 }
@@ -20,4 +26,26 @@ impl Error {
     {
         Self::Generic(message.into())
     }
+
+    /// A policy block that will never clear up on its own, e.g. a closed pull request
+    pub fn terminal_block<T>(reason: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        Self::PolicyBlock {
+            terminal: true,
+            reason: reason.into(),
+        }
+    }
+
+    /// A policy block that may clear up over time, e.g. missing approvals
+    pub fn transient_block<T>(reason: T) -> Self
+    where
+        T: Into<Cow<'static, str>>,
+    {
+        Self::PolicyBlock {
+            terminal: false,
+            reason: reason.into(),
+        }
+    }
 }
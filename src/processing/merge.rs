@@ -1,15 +1,22 @@
 use crate::config::MergeConfig;
 use crate::github::{
     client::{GithubClient, MergeRequestBody},
-    MergeMethod, PullRequest,
+    MergeMethod, PullRequest, PullRequestIdentifier, PullRequestState,
 };
 use crate::processing::Error;
 use async_trait::async_trait;
 use log::{info, warn};
+use std::time::Duration;
+use tokio::time::sleep;
 
 pub enum MergeResult {
-    Success,
+    /// The pull request was merged, creating the given merge commit if it was actually merged
+    Success {
+        merge_sha: Option<String>,
+    },
     Conflict,
+    /// GitHub's native auto-merge will merge the pull request
+    Deferred,
 }
 
 #[async_trait]
@@ -23,12 +30,49 @@ pub trait PullRequestMerger {
 
 pub struct DefaultPullRequestMerger {
     merge_methods: Vec<MergeMethod>,
+    verify_after: bool,
+    defer_to_native_auto_merge: bool,
 }
 
 impl DefaultPullRequestMerger {
+    const VERIFY_ATTEMPTS: u32 = 3;
+    const VERIFY_DELAY: Duration = Duration::from_secs(2);
+
     pub fn new(config: MergeConfig) -> Self {
         let merge_methods = Self::build_merge_methods(config.default_method);
-        Self { merge_methods }
+        Self {
+            merge_methods,
+            verify_after: config.verify_after,
+            defer_to_native_auto_merge: config.defer_to_native_auto_merge,
+        }
+    }
+
+    async fn verify_merged(
+        &self,
+        pull_request: &PullRequest,
+        github: &dyn GithubClient,
+    ) -> Result<(), Error> {
+        let identifier = PullRequestIdentifier {
+            owner: pull_request.base.repo.owner.login.clone(),
+            repo: pull_request.base.repo.name.clone(),
+            pull_number: pull_request.number,
+        };
+        for attempt in 1..=Self::VERIFY_ATTEMPTS {
+            let latest = github.pull_request_info(&identifier).await?;
+            if latest.merged && latest.state == PullRequestState::Closed {
+                info!("Verified pull request was merged");
+                return Ok(());
+            }
+            if attempt == Self::VERIFY_ATTEMPTS {
+                warn!(
+                    "Pull request doesn't look merged after merging it (state: {:?}, merged: {})",
+                    latest.state, latest.merged
+                );
+            } else {
+                sleep(Self::VERIFY_DELAY).await;
+            }
+        }
+        Ok(())
     }
 
     async fn merge_with_method(
@@ -36,7 +80,7 @@ impl DefaultPullRequestMerger {
         pull_request: &PullRequest,
         github: &dyn GithubClient,
         method: &MergeMethod,
-    ) -> Result<(), crate::client::Error> {
+    ) -> Result<String, crate::client::Error> {
         let commit_message = Self::build_merge_message(pull_request, method);
         let request_body = MergeRequestBody {
             sha: pull_request.head.sha.clone(),
@@ -44,10 +88,10 @@ impl DefaultPullRequestMerger {
             commit_message,
             merge_method: method.clone(),
         };
-        github
+        let result = github
             .merge_pull_request(pull_request, &request_body)
             .await?;
-        Ok(())
+        Ok(result.sha)
     }
 
     fn build_merge_message(pull_request: &PullRequest, method: &MergeMethod) -> Option<String> {
@@ -76,15 +120,40 @@ impl PullRequestMerger for DefaultPullRequestMerger {
         pull_request: &PullRequest,
         github: &dyn GithubClient,
     ) -> Result<MergeResult, Error> {
-        for method in &self.merge_methods {
+        if let Some(auto_merge) = &pull_request.auto_merge {
+            if self.defer_to_native_auto_merge {
+                info!(
+                    "Native auto-merge was enabled by {}; deferring",
+                    auto_merge.enabled_by.login
+                );
+                return Ok(MergeResult::Deferred);
+            }
+        }
+        let repo = &pull_request.base.repo;
+        let repo_info = github.repo_info(&repo.owner.login, &repo.name).await?;
+        let merge_methods = self
+            .merge_methods
+            .iter()
+            .filter(|method| repo_info.allows_merge_method(method));
+        for method in merge_methods {
             info!(
                 "Attempting to merge pull request using '{:?}' merge method",
                 method
             );
             match self.merge_with_method(pull_request, github, method).await {
-                Ok(_) => {
-                    info!("Pull request merged ✔️");
-                    return Ok(MergeResult::Success);
+                Ok(merge_sha) => {
+                    info!("Pull request merged ✔️ (merge commit {})", merge_sha);
+                    if self.verify_after {
+                        self.verify_merged(pull_request, github).await?;
+                    }
+                    return Ok(MergeResult::Success {
+                        merge_sha: Some(merge_sha),
+                    });
+                }
+                Err(e) if e.base_branch_modified() => {
+                    // Another method won't help, the pull request needs to be refreshed
+                    warn!("Base branch was modified while merging, retrying");
+                    return Ok(MergeResult::Conflict);
                 }
                 Err(e) if e.method_not_allowed() => {
                     warn!("Merge method '{:?}' not allowed", method);
@@ -112,14 +181,21 @@ impl PullRequestMerger for DummyPullRequestMerger {
         _github: &dyn GithubClient,
     ) -> Result<MergeResult, crate::processing::Error> {
         info!("Skipping pull request merge step");
-        Ok(MergeResult::Success)
+        Ok(MergeResult::Success { merge_sha: None })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::HttpConfig;
+    use crate::github::client::MockGithubClient;
+    use crate::github::DefaultGithubClient;
+    use crate::github::{AutoMerge, RepositoryInfo};
     use rstest::rstest;
+    use std::future;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
 
     #[rstest]
     fn test_build_merge_methods(
@@ -132,4 +208,127 @@ mod tests {
             assert!(methods.iter().position(|m| m == &method).is_some());
         }
     }
+
+    #[tokio::test]
+    async fn test_merge_defers_to_native_auto_merge() {
+        let github = MockGithubClient::default();
+        let pull_request = PullRequest {
+            auto_merge: Some(AutoMerge::default()),
+            ..Default::default()
+        };
+        let merger = DefaultPullRequestMerger::new(MergeConfig::default());
+        let result = merger.merge(&pull_request, &github).await.unwrap();
+        assert!(matches!(result, MergeResult::Deferred));
+    }
+
+    #[tokio::test]
+    async fn test_merge_skips_disallowed_methods() {
+        let mut github = MockGithubClient::default();
+        github.expect_repo_info().returning(|_, _| {
+            let info = RepositoryInfo {
+                allow_rebase_merge: true,
+                ..Default::default()
+            };
+            Box::pin(future::ready(Ok(info)))
+        });
+        github
+            .expect_merge_pull_request()
+            .withf(|_, body| body.merge_method == MergeMethod::Rebase)
+            .times(1)
+            .returning(|_, _| {
+                let result = crate::github::MergeResult {
+                    sha: "abc123".into(),
+                    merged: true,
+                    message: "Pull Request successfully merged".into(),
+                };
+                Box::pin(future::ready(Ok(result)))
+            });
+        let merger = DefaultPullRequestMerger::new(MergeConfig::default());
+        let result = merger
+            .merge(&PullRequest::default(), &github)
+            .await
+            .unwrap();
+        assert!(matches!(result, MergeResult::Success { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_merge_base_branch_modified() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/repos/potato/smasher"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "full_name": "potato/smasher",
+                "default_branch": "main",
+            })))
+            .mount(&server)
+            .await;
+        // No other merge methods are attempted
+        Mock::given(method("PUT"))
+            .and(path("/repos/potato/smasher/pulls/1337/merge"))
+            .respond_with(ResponseTemplate::new(405).set_body_json(serde_json::json!({
+                "message": "Base branch was modified. Review and try the merge again."
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mut pull_request = PullRequest {
+            number: 1337,
+            ..Default::default()
+        };
+        pull_request.base.repo.full_name = "potato/smasher".into();
+        pull_request.base.repo.name = "smasher".into();
+        pull_request.base.repo.owner.login = "potato".into();
+        let merger = DefaultPullRequestMerger::new(MergeConfig::default());
+        let github = DefaultGithubClient::new("mergebro", "token", &HttpConfig::default())
+            .with_api_base(server.uri());
+        let result = merger.merge(&pull_request, &github).await.unwrap();
+        assert!(matches!(result, MergeResult::Conflict));
+    }
+
+    #[tokio::test]
+    async fn test_merge_verify_after() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_repo_info()
+            .returning(|_, _| Box::pin(future::ready(Ok(RepositoryInfo::default()))));
+        github
+            .expect_merge_pull_request()
+            .times(1)
+            .returning(|_, _| {
+                let result = crate::github::MergeResult {
+                    sha: "abc123".into(),
+                    merged: true,
+                    message: "Pull Request successfully merged".into(),
+                };
+                Box::pin(future::ready(Ok(result)))
+            });
+        github
+            .expect_pull_request_info()
+            .withf(|id| id.owner == "potato" && id.repo == "smasher" && id.pull_number == 1337)
+            .times(1)
+            .returning(|_| {
+                let pull_request = PullRequest {
+                    state: PullRequestState::Closed,
+                    merged: true,
+                    ..Default::default()
+                };
+                Box::pin(future::ready(Ok(pull_request)))
+            });
+        let config = MergeConfig {
+            verify_after: true,
+            ..Default::default()
+        };
+        let mut pull_request = PullRequest {
+            number: 1337,
+            ..Default::default()
+        };
+        pull_request.base.repo.name = "smasher".into();
+        pull_request.base.repo.owner.login = "potato".into();
+        let merger = DefaultPullRequestMerger::new(config);
+        let result = merger.merge(&pull_request, &github).await.unwrap();
+        assert!(matches!(
+            result,
+            MergeResult::Success { merge_sha: Some(sha) } if sha == "abc123"
+        ));
+    }
 }
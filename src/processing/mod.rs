@@ -7,4 +7,6 @@ pub mod steps;
 pub use director::{Director, DirectorState};
 pub use error::Error;
 pub use merge::{DefaultPullRequestMerger, DummyPullRequestMerger, PullRequestMerger};
-pub use runner::{WorkflowRunner, WorkflowStatus};
+pub use runner::{
+    DryRunWorkflowRunner, FailedWorkflow, FailedWorkflows, WorkflowRunner, WorkflowStatus,
+};
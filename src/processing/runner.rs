@@ -1,14 +1,168 @@
 use super::Error;
 use async_trait::async_trait;
+use log::info;
 use reqwest::Url;
+use std::sync::Arc;
 
 #[async_trait]
 pub trait WorkflowRunner: Send + Sync {
-    async fn process_failed_jobs(&self, job_urls: &[Url]) -> Result<WorkflowStatus, Error>;
+    /// The kind of workflow this runner re-runs, e.g. "CircleCI workflow"
+    fn workflow_kind(&self) -> &'static str;
+
+    /// Finds the workflows the given failed jobs belong to, without re-running them. If `routed`
+    /// is set, the jobs were explicitly configured to belong to this runner so their URLs'
+    /// domains are not checked.
+    async fn find_failed_workflows(
+        &self,
+        job_urls: &[Url],
+        routed: bool,
+    ) -> Result<FailedWorkflows, Error>;
+
+    /// Re-runs a workflow found via `find_failed_workflows`
+    async fn rerun_workflow(&self, workflow: &FailedWorkflow) -> Result<(), Error>;
+
+    /// Re-runs the workflows the given failed jobs belong to
+    async fn process_failed_jobs(
+        &self,
+        job_urls: &[Url],
+        routed: bool,
+    ) -> Result<WorkflowStatus, Error> {
+        let workflows = self.find_failed_workflows(job_urls, routed).await?;
+        if workflows.rerunnable.is_empty() {
+            if workflows.skipped {
+                return Ok(WorkflowStatus::Skipped);
+            }
+            return Ok(WorkflowStatus::Success);
+        }
+        info!(
+            "Re-running {} failed {}s",
+            workflows.rerunnable.len(),
+            self.workflow_kind()
+        );
+        for workflow in &workflows.rerunnable {
+            self.rerun_workflow(workflow).await?;
+        }
+        Ok(WorkflowStatus::Triggered(workflows.rerunnable.len()))
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum WorkflowStatus {
     Success,
-    Triggered,
+    /// The given number of workflows were re-triggered
+    Triggered(usize),
+    /// There were failed workflows but none of them are allowed to be re-ran
+    Skipped,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct FailedWorkflows {
+    /// The failed workflows that are allowed to be re-ran
+    pub rerunnable: Vec<FailedWorkflow>,
+    /// Whether any failed workflows are not allowed to be re-ran
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedWorkflow {
+    /// The workflow's id, as understood by the runner that found it
+    pub id: String,
+    /// A human readable name for the workflow
+    pub name: String,
+    /// The URL of one of the workflow's failed jobs
+    pub job_url: Url,
+}
+
+/// A runner that looks up failed workflows as usual but only logs the ones it would re-run
+pub struct DryRunWorkflowRunner {
+    inner: Arc<dyn WorkflowRunner>,
+}
+
+impl DryRunWorkflowRunner {
+    pub fn new(inner: Arc<dyn WorkflowRunner>) -> Self {
+        Self { inner }
+    }
+}
+
+#[async_trait]
+impl WorkflowRunner for DryRunWorkflowRunner {
+    fn workflow_kind(&self) -> &'static str {
+        self.inner.workflow_kind()
+    }
+
+    async fn find_failed_workflows(
+        &self,
+        job_urls: &[Url],
+        routed: bool,
+    ) -> Result<FailedWorkflows, Error> {
+        self.inner.find_failed_workflows(job_urls, routed).await
+    }
+
+    async fn rerun_workflow(&self, workflow: &FailedWorkflow) -> Result<(), Error> {
+        info!(
+            "Dry run: not re-running {} '{}' for failed job {}",
+            self.inner.workflow_kind(),
+            workflow.name,
+            workflow.job_url
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingRunner {
+        reran: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl WorkflowRunner for RecordingRunner {
+        fn workflow_kind(&self) -> &'static str {
+            "test workflow"
+        }
+
+        async fn find_failed_workflows(
+            &self,
+            job_urls: &[Url],
+            _routed: bool,
+        ) -> Result<FailedWorkflows, Error> {
+            let rerunnable = job_urls
+                .iter()
+                .map(|url| FailedWorkflow {
+                    id: url.path().into(),
+                    name: url.path().into(),
+                    job_url: url.clone(),
+                })
+                .collect();
+            Ok(FailedWorkflows {
+                rerunnable,
+                skipped: false,
+            })
+        }
+
+        async fn rerun_workflow(&self, workflow: &FailedWorkflow) -> Result<(), Error> {
+            self.reran.lock().unwrap().push(workflow.id.clone());
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_only_skips_reruns() {
+        let inner = Arc::new(RecordingRunner::default());
+        let runner = DryRunWorkflowRunner::new(inner.clone());
+        let urls = [
+            Url::parse("https://example.com/1").unwrap(),
+            Url::parse("https://example.com/2").unwrap(),
+        ];
+        let status = runner.process_failed_jobs(&urls, false).await.unwrap();
+        assert_eq!(status, WorkflowStatus::Triggered(2));
+        assert!(inner.reran.lock().unwrap().is_empty());
+
+        inner.process_failed_jobs(&urls, false).await.unwrap();
+        assert_eq!(*inner.reran.lock().unwrap(), vec!["/1", "/2"]);
+    }
 }
@@ -1,18 +1,28 @@
 use super::{Error, WorkflowRunner, WorkflowStatus};
 use crate::{
-    config::{ReviewsConfig, StatusFailuresConfig},
+    common::GlobPattern,
+    config::{
+        BehindConfig, CommandConfig, CommitMessagesConfig, CurrentStateConfig, DeploymentConfig,
+        ExternalCommandConfig, FailureResetPolicy, ReviewsConfig, ReviewsMode,
+        StatusFailuresConfig, StatusesConfig, TaskListConfig, VersionBumpConfig,
+    },
     github::{
-        Branch, BranchProtection, GithubClient, MergeableState, PullRequest, PullRequestReview,
-        PullRequestState, ReviewState, StatusState, WorkflowRun, WorkflowRunConclusion,
+        Branch, BranchProtection, GithubClient, MergeMethod, MergeableState, PullRequest,
+        PullRequestReview, PullRequestState, ReviewState, StatusState, UpdateBranchMethod,
+        WorkflowRun, WorkflowRunConclusion,
     },
 };
 use async_trait::async_trait;
-use log::{info, warn};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, info, warn};
+use regex::Regex;
 use reqwest::Url;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
 use std::sync::Arc;
+use tokio::process::Command;
+use tokio::time::timeout;
 
 #[async_trait]
 pub trait Step: fmt::Display {
@@ -24,29 +34,56 @@ pub trait Step: fmt::Display {
 pub enum StepStatus {
     Passed,
     Waiting,
+    /// The pull request's branch was updated and GitHub needs to process it before going on
+    BranchUpdated,
+}
+
+/// Fetches the protection rules for a branch, if there are any
+async fn fetch_branch_protection(
+    github: &dyn GithubClient,
+    branch: &Branch,
+) -> Result<Option<BranchProtection>, Error> {
+    let branch_protection = github.branch_protection(branch).await;
+    match branch_protection {
+        Ok(branch_protection) => Ok(Some(branch_protection)),
+        Err(e) if e.not_found() => Ok(None),
+        Err(e) => Err(e.into()),
+    }
 }
 
 /// Checks whether a pull request is open and in a mergeable state.
 #[derive(Default)]
-pub struct CheckCurrentStateStep;
+pub struct CheckCurrentStateStep {
+    config: CurrentStateConfig,
+}
+
+impl CheckCurrentStateStep {
+    pub fn new(config: CurrentStateConfig) -> Self {
+        Self { config }
+    }
+}
 
 #[async_trait]
 impl Step for CheckCurrentStateStep {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
         match pull_request.state {
             PullRequestState::Open => {
-                if pull_request.draft {
-                    Err(Error::as_generic("pull request is a draft"))
+                if pull_request.base.repo.archived {
+                    Err(Error::terminal_block(
+                        "repository is archived, cannot merge",
+                    ))
+                } else if pull_request.draft && !self.config.mark_ready_when_passed {
+                    Err(Error::terminal_block("pull request is a draft"))
                 } else if matches!(pull_request.mergeable_state, MergeableState::Dirty) {
-                    Err(Error::as_generic("pull request has conflicts"))
+                    Err(Error::terminal_block("pull request has conflicts"))
                 } else {
                     Ok(StepStatus::Passed)
                 }
             }
             PullRequestState::Closed if pull_request.merged => {
-                Err(Error::as_generic("pull request is already merged"))
+                Err(Error::terminal_block("pull request is already merged"))
             }
-            PullRequestState::Closed => Err(Error::as_generic("pull request is closed")),
+            PullRequestState::Closed => Err(Error::terminal_block("pull request is closed")),
             PullRequestState::Unknown => Err(Error::UnsupportedPullRequestState(
                 "pull request state is unknown".into(),
             )),
@@ -60,6 +97,444 @@ impl fmt::Display for CheckCurrentStateStep {
     }
 }
 
+/// Waits until an authorized user comments the trigger command on a pull request
+pub struct CheckMergeCommand {
+    github: Arc<dyn GithubClient>,
+    config: CommandConfig,
+    triggered: bool,
+}
+
+impl CheckMergeCommand {
+    pub fn new(github: Arc<dyn GithubClient>, config: CommandConfig) -> Self {
+        Self {
+            github,
+            config,
+            triggered: false,
+        }
+    }
+}
+
+#[async_trait]
+impl Step for CheckMergeCommand {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if self.triggered {
+            return Ok(StepStatus::Passed);
+        }
+        let comments = self.github.list_comments(pull_request).await?;
+        let command = comments.iter().rev().find(|comment| {
+            comment.body.trim() == self.config.trigger
+                && self.config.authorized_users.contains(&comment.user.login)
+        });
+        match command {
+            Some(comment) => {
+                info!("Merge command triggered by {}", comment.user.login);
+                self.triggered = true;
+                Ok(StepStatus::Passed)
+            }
+            None => Err(Error::transient_block(format!(
+                "waiting for an authorized user to comment '{}'",
+                self.config.trigger
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for CheckMergeCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check merge command")
+    }
+}
+
+/// Checks whether a pull request targets a base branch that requires an explicit opt-in to be
+/// merged into
+pub struct CheckSensitiveBase {
+    sensitive_branches: Vec<GlobPattern>,
+}
+
+impl CheckSensitiveBase {
+    pub fn new(sensitive_branches: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let sensitive_branches = sensitive_branches
+            .iter()
+            .map(|pattern| pattern.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { sensitive_branches })
+    }
+}
+
+#[async_trait]
+impl Step for CheckSensitiveBase {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let base = &pull_request.base.name;
+        match self.sensitive_branches.iter().find(|p| p.matches(base)) {
+            Some(pattern) => Err(Error::terminal_block(format!(
+                "pull request targets sensitive base branch '{}' (matches '{}'), use --allow-sensitive-base to merge it",
+                base, pattern
+            ))),
+            None => Ok(StepStatus::Passed),
+        }
+    }
+}
+
+impl fmt::Display for CheckSensitiveBase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check sensitive base branch")
+    }
+}
+
+/// Checks whether a pull request declares a valid version bump in either its title or labels
+pub struct CheckVersionBump {
+    github: Arc<dyn GithubClient>,
+    config: VersionBumpConfig,
+}
+
+impl CheckVersionBump {
+    pub fn new(github: Arc<dyn GithubClient>, config: VersionBumpConfig) -> Self {
+        Self { github, config }
+    }
+
+    /// Finds the first allowed version bump declared in the title or the labels, in that order
+    fn find_version_bump<'a>(&self, pull_request: &'a PullRequest) -> Result<&'a str, Error> {
+        let from_title = self
+            .config
+            .pattern
+            .captures(&pull_request.title)
+            .and_then(|captures| captures.get(1))
+            .map(|m| m.as_str());
+        let from_labels = pull_request.labels.iter().filter_map(|label| {
+            let prefix = self.config.label_prefix.as_ref()?;
+            label.name.strip_prefix(prefix.as_str())
+        });
+        let mut declared = from_title.into_iter().chain(from_labels).peekable();
+        let invalid = declared.peek().copied();
+        match declared.find(|bump| self.config.allowed.iter().any(|allowed| allowed == bump)) {
+            Some(bump) => Ok(bump),
+            None => match invalid {
+                Some(bump) => Err(Error::transient_block(format!(
+                    "invalid version bump '{}' (allowed: {})",
+                    bump,
+                    self.config.allowed.join(", ")
+                ))),
+                None => Err(Error::transient_block(
+                    "pull request does not declare a version bump",
+                )),
+            },
+        }
+    }
+
+    async fn version_files_changed(&self, pull_request: &PullRequest) -> Result<bool, Error> {
+        let files = self.github.pull_request_files(pull_request).await?;
+        Ok(files
+            .iter()
+            .any(|file| self.config.version_files.contains(&file.filename)))
+    }
+}
+
+#[async_trait]
+impl Step for CheckVersionBump {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let version_bump = self.find_version_bump(pull_request)?;
+        if !self.config.version_files.is_empty()
+            && !self.version_files_changed(pull_request).await?
+        {
+            return Err(Error::transient_block(format!(
+                "pull request declares a '{}' version bump but no version files were changed",
+                version_bump
+            )));
+        }
+        Ok(StepStatus::Passed)
+    }
+}
+
+impl fmt::Display for CheckVersionBump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check version bump")
+    }
+}
+
+/// Checks whether every commit message in a pull request matches a pattern
+pub struct CheckCommitMessages {
+    github: Arc<dyn GithubClient>,
+    pattern: Regex,
+    skip_if_squashed: bool,
+}
+
+impl CheckCommitMessages {
+    pub fn new(
+        github: Arc<dyn GithubClient>,
+        config: CommitMessagesConfig,
+        merge_method: &MergeMethod,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let pattern = Regex::new(&config.pattern)?;
+        let skip_if_squashed =
+            config.only_preserving_methods && matches!(merge_method, MergeMethod::Squash);
+        Ok(Self {
+            github,
+            pattern,
+            skip_if_squashed,
+        })
+    }
+
+    /// Squashing discards the individual commit messages so they're irrelevant, but the merger
+    /// falls back to preserving methods if the repository doesn't allow squashing
+    async fn will_squash(&self, pull_request: &PullRequest) -> Result<bool, Error> {
+        if !self.skip_if_squashed {
+            return Ok(false);
+        }
+        let repo = &pull_request.base.repo;
+        let info = self.github.repo_info(&repo.owner.login, &repo.name).await?;
+        Ok(info.allow_squash_merge)
+    }
+}
+
+#[async_trait]
+impl Step for CheckCommitMessages {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if self.will_squash(pull_request).await? {
+            return Ok(StepStatus::Passed);
+        }
+        let commits = self.github.pull_request_commits(pull_request).await?;
+        let offending_commits: Vec<_> = commits
+            .iter()
+            .filter(|commit| !self.pattern.is_match(&commit.commit.message))
+            .map(|commit| {
+                let sha = commit.sha.get(..7).unwrap_or(&commit.sha);
+                let title = commit.commit.message.lines().next().unwrap_or_default();
+                format!("{} ({})", sha, title)
+            })
+            .collect();
+        if offending_commits.is_empty() {
+            Ok(StepStatus::Passed)
+        } else {
+            Err(Error::terminal_block(format!(
+                "commit messages don't match the required pattern: {}",
+                offending_commits.join(", ")
+            )))
+        }
+    }
+}
+
+impl fmt::Display for CheckCommitMessages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check commit messages")
+    }
+}
+
+/// Checks whether the task list items in a pull request's body are checked
+pub struct CheckTaskList {
+    config: TaskListConfig,
+    item_pattern: Regex,
+}
+
+impl CheckTaskList {
+    pub fn new(config: TaskListConfig) -> Self {
+        let item_pattern = Regex::new(r"^\s*[-*+]\s+\[([ xX])\]").expect("invalid task list regex");
+        Self {
+            config,
+            item_pattern,
+        }
+    }
+
+    /// Returns the number of checked and unchecked items in the given markdown text
+    fn count_items(&self, body: &str) -> (u32, u32) {
+        let mut checked = 0;
+        let mut unchecked = 0;
+        for captures in body
+            .lines()
+            .filter_map(|line| self.item_pattern.captures(line))
+        {
+            match &captures[1] {
+                " " => unchecked += 1,
+                _ => checked += 1,
+            }
+        }
+        (checked, unchecked)
+    }
+}
+
+#[async_trait]
+impl Step for CheckTaskList {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let body = pull_request.body.as_deref().unwrap_or_default();
+        let (checked, unchecked) = self.count_items(body);
+        if self.config.require_all_checked && unchecked > 0 {
+            Err(Error::transient_block(format!(
+                "{} task list items are unchecked",
+                unchecked
+            )))
+        } else if checked < self.config.min_checked {
+            Err(Error::transient_block(format!(
+                "{} task list items are checked but {} are required",
+                checked, self.config.min_checked
+            )))
+        } else {
+            Ok(StepStatus::Passed)
+        }
+    }
+}
+
+impl fmt::Display for CheckTaskList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check task list")
+    }
+}
+
+/// Checks that there's no deployment to an environment in progress
+pub struct CheckNoActiveDeployment {
+    github: Arc<dyn GithubClient>,
+    config: DeploymentConfig,
+}
+
+impl CheckNoActiveDeployment {
+    pub fn new(github: Arc<dyn GithubClient>, config: DeploymentConfig) -> Self {
+        Self { github, config }
+    }
+}
+
+#[async_trait]
+impl Step for CheckNoActiveDeployment {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let repo = &pull_request.base.repo;
+        let deployments = self
+            .github
+            .deployments(repo, &self.config.environment)
+            .await?;
+        // Only the latest deployment matters, anything queued after it would be newer
+        let deployment = match deployments.first() {
+            Some(deployment) => deployment,
+            None => return Ok(StepStatus::Passed),
+        };
+        let statuses = self.github.deployment_statuses(repo, deployment.id).await?;
+        // Deployments that never reported a status are considered idle, otherwise a deployment
+        // that was created but never started would block every merge
+        match statuses.first() {
+            Some(status) if status.state.is_active() => Err(Error::transient_block(format!(
+                "deployment of {} to '{}' is {:?}",
+                deployment.sha, self.config.environment, status.state
+            ))),
+            _ => Ok(StepStatus::Passed),
+        }
+    }
+}
+
+impl fmt::Display for CheckNoActiveDeployment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check no active deployment")
+    }
+}
+
+/// Runs a user provided command, using its exit code to determine whether the check passed.
+///
+/// The command gets information about the pull request via `MERGEBRO_*` environment variables.
+pub struct CheckExternalCommand {
+    config: ExternalCommandConfig,
+    dry_run: bool,
+}
+
+impl CheckExternalCommand {
+    pub fn new(config: ExternalCommandConfig) -> Self {
+        Self {
+            config,
+            dry_run: false,
+        }
+    }
+
+    /// Only logs the command instead of running it, as it may change state
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    fn build_command(&self, pull_request: &PullRequest) -> Command {
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg(&self.config.command)
+            .env("MERGEBRO_REPO", &pull_request.base.repo.full_name)
+            .env("MERGEBRO_PR_NUMBER", pull_request.number.to_string())
+            .env("MERGEBRO_PR_TITLE", &pull_request.title)
+            .env("MERGEBRO_HEAD_SHA", &pull_request.head.sha)
+            .env("MERGEBRO_HEAD_BRANCH", &pull_request.head.name)
+            .env("MERGEBRO_BASE_BRANCH", &pull_request.base.name)
+            // Makes sure the command doesn't outlive a timeout
+            .kill_on_drop(true);
+        command
+    }
+}
+
+#[async_trait]
+impl Step for CheckExternalCommand {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if self.dry_run {
+            info!(
+                "Dry run: not running command '{}': {}",
+                self.config.name, self.config.command
+            );
+            return Ok(StepStatus::Passed);
+        }
+        let mut command = self.build_command(pull_request);
+        let max_duration = std::time::Duration::from_secs(self.config.timeout_seconds);
+        let output = timeout(max_duration, command.output())
+            .await
+            .map_err(|_| {
+                Error::as_generic(format!(
+                    "command '{}' timed out after {} seconds",
+                    self.config.name, self.config.timeout_seconds
+                ))
+            })?
+            .map_err(|e| Error::as_generic(format!("failed to run command: {}", e)))?;
+        match output.status.code() {
+            Some(0) => Ok(StepStatus::Passed),
+            Some(code) if code == self.config.waiting_exit_code => Ok(StepStatus::Waiting),
+            code => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(Error::as_generic(format!(
+                    "command '{}' failed with exit code {}: {}",
+                    self.config.name,
+                    code.map(|code| code.to_string())
+                        .unwrap_or_else(|| "unknown".into()),
+                    stderr.trim()
+                )))
+            }
+        }
+    }
+}
+
+impl fmt::Display for CheckExternalCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "run external command '{}'", self.config.name)
+    }
+}
+
+/// Marks a draft pull request as ready for review. This is meant to be the last step so it only
+/// runs once every other check has passed.
+pub struct MarkReadyForReview {
+    github: Arc<dyn GithubClient>,
+}
+
+impl MarkReadyForReview {
+    pub fn new(github: Arc<dyn GithubClient>) -> Self {
+        Self { github }
+    }
+}
+
+#[async_trait]
+impl Step for MarkReadyForReview {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if !pull_request.draft {
+            return Ok(StepStatus::Passed);
+        }
+        warn!("Pull request is a draft, marking it as ready for review");
+        self.github.mark_ready_for_review(pull_request).await?;
+        Ok(StepStatus::Waiting)
+    }
+}
+
+impl fmt::Display for MarkReadyForReview {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mark ready for review")
+    }
+}
+
 /// Checks whether a pull request is approved by however many people its branch protection
 /// rules require
 pub struct CheckReviewsStep {
@@ -75,19 +550,11 @@ impl CheckReviewsStep {
         Ok(Self { github, reviews })
     }
 
-    async fn fetch_branch_protection(
-        &self,
-        branch: &Branch,
-    ) -> Result<Option<BranchProtection>, Error> {
-        let branch_protection = self.github.branch_protection(branch).await;
-        match branch_protection {
-            Ok(branch_protection) => Ok(Some(branch_protection)),
-            Err(e) if e.not_found() => Ok(None),
-            Err(e) => Err(e.into()),
-        }
-    }
-
     fn compute_approvals(reviews: &[PullRequestReview]) -> usize {
+        // The latest decision per user is the one that counts, regardless of the order the API
+        // returned the reviews in
+        let mut reviews: Vec<_> = reviews.iter().collect();
+        reviews.sort_by_key(|review| review.submitted_at);
         let mut users_approved = HashSet::new();
         for review in reviews {
             match review.state {
@@ -95,7 +562,8 @@ impl CheckReviewsStep {
                 ReviewState::ChangesRequested | ReviewState::Dismissed => {
                     users_approved.remove(&review.user.login)
                 }
-                _ => continue,
+                // Comments and the reviewer's own pending drafts don't change their decision
+                ReviewState::Commented | ReviewState::Pending => continue,
             };
         }
         users_approved.len()
@@ -103,9 +571,13 @@ impl CheckReviewsStep {
 
     fn required_approvals(&self, branch_protection: Option<BranchProtection>) -> u32 {
         let configured_approvals = self.reviews.approvals;
-        match branch_protection {
-            Some(protection) => protection.reviews.approvals.max(configured_approvals),
-            None => configured_approvals,
+        let protection_approvals = branch_protection.map(|protection| protection.reviews.approvals);
+        match self.reviews.mode {
+            ReviewsMode::Max => protection_approvals
+                .unwrap_or_default()
+                .max(configured_approvals),
+            ReviewsMode::BranchProtectionOnly => protection_approvals.unwrap_or_default(),
+            ReviewsMode::ConfigOnly => configured_approvals,
         }
     }
 }
@@ -113,17 +585,27 @@ impl CheckReviewsStep {
 #[async_trait]
 impl Step for CheckReviewsStep {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        let branch_protection = self.fetch_branch_protection(&pull_request.base).await?;
+        let branch_protection = fetch_branch_protection(&*self.github, &pull_request.base).await?;
         let approvals_needed = self.required_approvals(branch_protection) as usize;
         let reviews = self.github.pull_request_reviews(pull_request).await?;
         let total_users_approved = Self::compute_approvals(&reviews);
+        debug!(
+            "Pull request has {} approvals out of {} reviews, {} needed",
+            total_users_approved,
+            reviews.len(),
+            approvals_needed
+        );
 
         if total_users_approved < approvals_needed {
             let reason = format!(
                 "not enough approvals (need {}, have {})",
                 approvals_needed, total_users_approved
             );
-            Err(Error::as_generic(reason))
+            if self.reviews.abort_on_shortfall {
+                Err(Error::terminal_block(reason))
+            } else {
+                Err(Error::transient_block(reason))
+            }
         } else {
             Ok(StepStatus::Passed)
         }
@@ -139,11 +621,80 @@ impl fmt::Display for CheckReviewsStep {
 /// Checks whether a pull request is behind master, and updates it otherwise
 pub struct CheckBehindMaster {
     github: Arc<dyn GithubClient>,
+    config: BehindConfig,
+    updated_sha: Option<String>,
+    update_attempts: u32,
 }
 
 impl CheckBehindMaster {
-    pub fn new(github: Arc<dyn GithubClient>) -> Self {
-        Self { github }
+    pub fn new(github: Arc<dyn GithubClient>, config: BehindConfig) -> Self {
+        Self {
+            github,
+            config,
+            updated_sha: None,
+            update_attempts: 0,
+        }
+    }
+
+    fn track_update_attempt(&mut self, pull_request: &PullRequest) -> Result<(), Error> {
+        if self.updated_sha.is_some() {
+            self.update_attempts += 1;
+        }
+        if let Some(max_attempts) = self.config.max_update_attempts {
+            if self.update_attempts >= max_attempts {
+                return Err(Error::terminal_block(
+                    "branch cannot keep up with base; too active to auto-merge",
+                ));
+            }
+        }
+        self.updated_sha = Some(pull_request.head.sha.clone());
+        Ok(())
+    }
+
+    async fn check_compare_ref(
+        &self,
+        pull_request: &PullRequest,
+        compare_ref: &str,
+    ) -> Result<StepStatus, Error> {
+        let comparison = self
+            .github
+            .compare_commits(&pull_request.base.repo, compare_ref, &pull_request.head.sha)
+            .await?;
+        if comparison.behind_by == 0 {
+            return Ok(StepStatus::Passed);
+        }
+        // The ref lives in the base repo so it can't be merged into a fork's branch
+        if pull_request.head.repo.full_name != pull_request.base.repo.full_name {
+            return Err(Error::terminal_block(format!(
+                "pull request branch is {} commits behind '{}' and can't be updated as it belongs to a fork",
+                comparison.behind_by, compare_ref
+            )));
+        }
+        warn!(
+            "Pull request branch is {} commits behind '{}', updating it",
+            comparison.behind_by, compare_ref
+        );
+        let result = self
+            .github
+            .merge_into_branch(&pull_request.head, compare_ref)
+            .await;
+        match result {
+            Ok(_) => Ok(StepStatus::BranchUpdated),
+            Err(e) if e.conflict() => Err(Error::as_generic(format!(
+                "pull request branch has conflicts with '{}'",
+                compare_ref
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn update_method(&self, pull_request: &PullRequest) -> Result<UpdateBranchMethod, Error> {
+        let branch_protection = fetch_branch_protection(&*self.github, &pull_request.base).await?;
+        if branch_protection.is_some_and(|p| p.required_linear_history.enabled) {
+            Ok(UpdateBranchMethod::Rebase)
+        } else {
+            Ok(UpdateBranchMethod::Merge)
+        }
     }
 }
 
@@ -151,13 +702,32 @@ impl CheckBehindMaster {
 impl Step for CheckBehindMaster {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
         if !matches!(pull_request.mergeable_state, MergeableState::Behind) {
-            return Ok(StepStatus::Passed);
+            // Right after an update the state goes through unknown, blocked, etc, so only a clean
+            // state proves the branch caught up with its base
+            if matches!(pull_request.mergeable_state, MergeableState::Clean) {
+                self.updated_sha = None;
+                self.update_attempts = 0;
+            }
+            return match &self.config.compare_ref {
+                Some(compare_ref) => self.check_compare_ref(pull_request, compare_ref).await,
+                None => Ok(StepStatus::Passed),
+            };
+        }
+        // The head only changes once the previous update lands
+        if self.updated_sha.as_ref() == Some(&pull_request.head.sha) {
+            info!("Waiting for the branch update to land");
+            return Ok(StepStatus::Waiting);
         }
-        warn!("Pull request branch is behind master, updating it");
+        self.track_update_attempt(pull_request)?;
+        let method = self.update_method(pull_request).await?;
+        warn!(
+            "Pull request branch is behind master, updating it using {:?}",
+            method
+        );
 
-        let result = self.github.update_branch(pull_request).await;
+        let result = self.github.update_branch(pull_request, method).await;
         match result {
-            Ok(_) => Ok(StepStatus::Waiting),
+            Ok(_) => Ok(StepStatus::BranchUpdated),
             // Technically we should retry but this means the head sha has _just_ changed so
             // odds are someone just did it manually which means we're waiting either way
             Err(e) if e.unprocessable_entity() => Ok(StepStatus::Waiting),
@@ -172,13 +742,85 @@ impl fmt::Display for CheckBehindMaster {
     }
 }
 
+/// Checks whether every status check required by the base branch's protection rules has been
+/// reported at least once for the current head
+pub struct CheckChecksRegistered {
+    github: Arc<dyn GithubClient>,
+}
+
+impl CheckChecksRegistered {
+    pub fn new(github: Arc<dyn GithubClient>) -> Self {
+        Self { github }
+    }
+
+    async fn fetch_reported_checks(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<HashSet<String>, Error> {
+        let check_runs = self.github.check_runs(pull_request).await?;
+        let mut reported: HashSet<_> = check_runs
+            .check_runs
+            .into_iter()
+            .map(|run| run.name)
+            .collect();
+        // The combined status tells whether any statuses were reported without listing them
+        let combined_status = self.github.combined_status(pull_request).await?;
+        if combined_status.total_count > 0 {
+            let statuses = self.github.pull_request_statuses(pull_request).await?;
+            reported.extend(statuses.into_iter().map(|status| status.context));
+        }
+        Ok(reported)
+    }
+}
+
+#[async_trait]
+impl Step for CheckChecksRegistered {
+    async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        let branch_protection = fetch_branch_protection(&*self.github, &pull_request.base).await?;
+        let required_checks = match branch_protection.and_then(|p| p.required_status_checks) {
+            Some(checks) if !checks.contexts.is_empty() => checks.contexts,
+            _ => return Ok(StepStatus::Passed),
+        };
+        let reported_checks = self.fetch_reported_checks(pull_request).await?;
+        let missing_checks: Vec<_> = required_checks
+            .iter()
+            .filter(|check| !reported_checks.contains(*check))
+            .collect();
+        match missing_checks.len() {
+            0 => Ok(StepStatus::Passed),
+            1 => Err(Error::transient_block(format!(
+                "required check '{}' has not been reported yet",
+                missing_checks[0]
+            ))),
+            count => Err(Error::transient_block(format!(
+                "{} required checks have not been reported yet",
+                count
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for CheckChecksRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "check required checks are registered")
+    }
+}
+
 /// Checks whether the build for a pull request failed, re-triggering CI runs if needed
 pub struct CheckBuildFailed {
     github: Arc<dyn GithubClient>,
     workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
     last_head_hash: Option<String>,
     status_failures_config: HashMap<String, StatusFailuresConfig>,
-    status_failures: HashMap<String, u32>,
+    statuses_config: StatusesConfig,
+    status_failures: HashMap<String, Vec<DateTime<Utc>>>,
+    // Status checks that succeeded on any of the pull request's commits, as of some head
+    prior_successes: Option<PriorSuccesses>,
+    // Everything that was re-triggered during the current iteration
+    retriggered: Vec<String>,
+    // Runners explicitly configured for specific status checks
+    status_runners: HashMap<String, Arc<dyn WorkflowRunner>>,
+    ignored_actions: Vec<GlobPattern>,
 }
 
 impl CheckBuildFailed {
@@ -186,24 +828,52 @@ impl CheckBuildFailed {
         github: Arc<dyn GithubClient>,
         workflow_runners: Vec<Arc<dyn WorkflowRunner>>,
         status_failures_config: HashMap<String, StatusFailuresConfig>,
+        statuses_config: StatusesConfig,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         Ok(Self {
             github,
             workflow_runners,
             last_head_hash: None,
             status_failures_config,
+            statuses_config,
             status_failures: HashMap::default(),
+            prior_successes: None,
+            retriggered: Vec::new(),
+            status_runners: HashMap::default(),
+            ignored_actions: Vec::new(),
         })
     }
 
-    async fn check_actions(&self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+    /// Re-runs the given status checks using the given runners rather than picking them based on
+    /// the status' URL
+    pub fn with_status_runners(
+        mut self,
+        status_runners: HashMap<String, Arc<dyn WorkflowRunner>>,
+    ) -> Self {
+        self.status_runners = status_runners;
+        self
+    }
+
+    /// Ignores Actions workflows whose names match any of the given glob patterns
+    pub fn with_ignored_actions(
+        mut self,
+        ignored_actions: &[String],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        self.ignored_actions = ignored_actions
+            .iter()
+            .map(|pattern| pattern.parse())
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    async fn check_actions(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
         let split_runs = self.fetch_action_runs(pull_request).await?;
         match split_runs.pending.len() {
             0 => {
                 if split_runs.failed.is_empty() {
                     return Ok(StepStatus::Passed);
                 }
-                self.process_failed_actions(pull_request, &split_runs.failed)
+                self.process_failed_actions(pull_request, &split_runs)
                     .await?;
             }
             1 => info!(
@@ -216,13 +886,25 @@ impl CheckBuildFailed {
     }
 
     async fn check_statuses(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        // The combined status summarizes every status check, so they only need to be looked at
+        // individually if any of them didn't succeed. Note that it's pending if there's none.
+        let combined_status = self.github.combined_status(pull_request).await?;
+        if combined_status.total_count == 0 || combined_status.state == StatusState::Success {
+            return Ok(StepStatus::Passed);
+        }
         let summaries = self.fetch_status_summaries(pull_request).await?;
+        debug!(
+            "External jobs pending: [{}], failed: [{}]",
+            Self::join_names(&summaries.pending),
+            Self::join_names(&summaries.failed)
+        );
         match summaries.pending.len() {
             0 => {
                 if summaries.failed.is_empty() {
                     return Ok(StepStatus::Passed);
                 }
-                self.process_failed_statuses(summaries.failed).await?;
+                self.process_failed_statuses(pull_request, summaries.failed)
+                    .await?;
             }
             1 => {
                 info!(
@@ -237,18 +919,51 @@ impl CheckBuildFailed {
         Ok(StepStatus::Waiting)
     }
 
-    async fn process_failed_statuses(&mut self, statuses: Vec<StatusSummary>) -> Result<(), Error> {
+    async fn process_failed_statuses(
+        &mut self,
+        pull_request: &PullRequest,
+        statuses: Vec<StatusSummary>,
+    ) -> Result<(), Error> {
         warn!("Processing {} failed external jobs", statuses.len());
         self.check_max_failures(&statuses)?;
-        let failed_job_urls: Vec<_> = statuses.into_iter().map(|summary| summary.url).collect();
-        let mut total_triggered = 0;
-        for runner in &self.workflow_runners {
-            if runner.process_failed_jobs(&failed_job_urls).await? == WorkflowStatus::Triggered {
-                total_triggered += 1;
+        self.check_prior_success(pull_request, &statuses).await?;
+        let (routed, unrouted): (Vec<_>, Vec<_>) = statuses
+            .into_iter()
+            .partition(|summary| self.status_runners.contains_key(&summary.name));
+        let mut results = Vec::new();
+        for summary in routed {
+            let runner = &self.status_runners[&summary.name];
+            let status = runner.process_failed_jobs(&[summary.url], true).await?;
+            results.push((runner.workflow_kind(), status));
+        }
+        let failed_job_urls: Vec<_> = unrouted.into_iter().map(|summary| summary.url).collect();
+        if !failed_job_urls.is_empty() {
+            for runner in &self.workflow_runners {
+                let status = runner.process_failed_jobs(&failed_job_urls, false).await?;
+                results.push((runner.workflow_kind(), status));
             }
         }
-        if total_triggered == 0 {
-            // There's failed jobs but we don't know how to re-trigger them. e.g. we don't support
+        let mut total_triggered = 0;
+        let mut total_skipped = 0;
+        for (workflow_kind, status) in results {
+            match status {
+                WorkflowStatus::Triggered(count) => {
+                    total_triggered += 1;
+                    let plural = if count == 1 { "" } else { "s" };
+                    self.retriggered
+                        .push(format!("{} {}{}", count, workflow_kind, plural));
+                }
+                WorkflowStatus::Skipped => total_skipped += 1,
+                WorkflowStatus::Success => (),
+            };
+        }
+        if total_triggered == 0 && total_skipped > 0 {
+            return Err(Error::as_generic(
+                "failed jobs belong to workflows that are not allowed to be re-ran",
+            ));
+        }
+        if total_triggered == 0 {
+            // There's failed jobs but we don't know how to re-trigger them. e.g. we don't support
             // whatever service they're being ran on.
             return Err(Error::as_generic(
                 "failed jobs belong to unknown external services",
@@ -257,15 +972,96 @@ impl CheckBuildFailed {
         Ok(())
     }
 
+    async fn check_green_stability(&self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
+        if self.statuses_config.green_stability_seconds == 0 {
+            return Ok(StepStatus::Passed);
+        }
+        let stability = Duration::seconds(self.statuses_config.green_stability_seconds as i64);
+        let summaries = self.fetch_status_summaries(pull_request).await?;
+        let now = Utc::now();
+        match summaries
+            .green_since
+            .iter()
+            .find(|(_, since)| now - **since < stability)
+        {
+            Some((name, since)) => {
+                info!(
+                    "Waiting for external job '{}' to be green for {} more seconds",
+                    name,
+                    (stability - (now - *since)).num_seconds()
+                );
+                Ok(StepStatus::Waiting)
+            }
+            None => Ok(StepStatus::Passed),
+        }
+    }
+
+    async fn check_prior_success(
+        &mut self,
+        pull_request: &PullRequest,
+        failed_statuses: &[StatusSummary],
+    ) -> Result<(), Error> {
+        if !self.statuses_config.require_prior_success_to_retrigger {
+            return Ok(());
+        }
+        // The commit history only changes along with the head so it's only loaded once per head
+        let prior_successes = match self.prior_successes.take() {
+            Some(prior_successes) if prior_successes.head_sha == pull_request.head.sha => {
+                prior_successes
+            }
+            _ => self.load_prior_successes(pull_request).await?,
+        };
+        let never_succeeded = failed_statuses
+            .iter()
+            .find(|status| !prior_successes.statuses.contains(&status.name))
+            .map(|status| status.name.clone());
+        self.prior_successes = Some(prior_successes);
+        match never_succeeded {
+            Some(name) => Err(Error::as_generic(format!(
+                "status check '{}' has never succeeded, not re-triggering it",
+                name
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Loads the status checks that succeeded on any of the pull request's commits
+    async fn load_prior_successes(
+        &self,
+        pull_request: &PullRequest,
+    ) -> Result<PriorSuccesses, Error> {
+        let repo = &pull_request.base.repo;
+        let mut statuses = HashSet::new();
+        for commit in self.github.pull_request_commits(pull_request).await? {
+            let commit_statuses = self.github.commit_statuses(repo, &commit.sha).await?;
+            statuses.extend(
+                commit_statuses
+                    .into_iter()
+                    .filter(|status| status.state == StatusState::Success)
+                    .map(|status| status.context),
+            );
+        }
+        Ok(PriorSuccesses {
+            head_sha: pull_request.head.sha.clone(),
+            statuses,
+        })
+    }
+
     fn check_max_failures(&mut self, failed_statuses: &[StatusSummary]) -> Result<(), Error> {
+        let window = Duration::seconds(self.statuses_config.failure_window_seconds as i64);
+        let now = Utc::now();
         for status in failed_statuses {
             if let Some(config) = self.status_failures_config.get(&status.name) {
-                let failures = self.status_failures.entry(status.name.clone()).or_insert(0);
-                *failures += 1;
-                if *failures >= config.max_failures {
+                let failures = self.status_failures.entry(status.name.clone()).or_default();
+                failures.push(status.created_at);
+                if self.statuses_config.failure_reset == FailureResetPolicy::TimeDecay {
+                    failures.retain(|failed_at| now - *failed_at < window);
+                }
+                if failures.len() as u32 >= config.max_failures {
                     return Err(Error::as_generic(format!(
                         "status check '{}' reached {} failures",
-                        status.name, failures
+                        status.name,
+                        failures.len()
                     )));
                 }
             }
@@ -279,8 +1075,18 @@ impl CheckBuildFailed {
     ) -> Result<StatusSummaries, Error> {
         let statuses = self.github.pull_request_statuses(pull_request).await?;
         let mut last_run_per_status = HashMap::new();
+        let mut green_since = HashMap::new();
+        let mut turned_green = HashSet::new();
         // Note: there's 0 docs on this so it's unclear but it seems `context` is the thing to group by.
         for status in statuses {
+            if status.state == StatusState::Success {
+                // Statuses are sorted newest first so keep going back until it wasn't green
+                if !turned_green.contains(&status.context) {
+                    green_since.insert(status.context.clone(), status.created_at);
+                }
+            } else {
+                turned_green.insert(status.context.clone());
+            }
             last_run_per_status
                 .entry(status.context.clone())
                 .or_insert(status);
@@ -292,6 +1098,7 @@ impl CheckBuildFailed {
             let summary = StatusSummary {
                 url,
                 name: status.context,
+                created_at: status.created_at,
             };
             match status.state {
                 StatusState::Failure => failed.push(summary),
@@ -299,7 +1106,11 @@ impl CheckBuildFailed {
                 _ => (),
             };
         }
-        Ok(StatusSummaries { pending, failed })
+        Ok(StatusSummaries {
+            pending,
+            failed,
+            green_since,
+        })
     }
 
     async fn fetch_action_runs(
@@ -308,7 +1119,16 @@ impl CheckBuildFailed {
     ) -> Result<SplitActionRuns, Error> {
         let action_runs = self.github.action_runs(pull_request).await?;
         let mut last_run_per_workflow = HashMap::new();
+        let mut succeeded = HashSet::new();
         for run in action_runs.workflow_runs {
+            if self.ignored_actions.iter().any(|p| p.matches(&run.name)) {
+                debug!("Ignoring actions workflow '{}'", run.name);
+                continue;
+            }
+            // Runs for older commits are only used to tell whether a workflow ever succeeded
+            if run.conclusion == Some(WorkflowRunConclusion::Success) {
+                succeeded.insert(run.workflow_id);
+            }
             if run.head_sha != pull_request.head.sha {
                 continue;
             }
@@ -323,23 +1143,63 @@ impl CheckBuildFailed {
                 _ => (),
             }
         }
-        Ok(SplitActionRuns { pending, failed })
+        Ok(SplitActionRuns {
+            pending,
+            failed,
+            succeeded,
+        })
     }
 
     async fn process_failed_actions(
-        &self,
+        &mut self,
         pull_request: &PullRequest,
-        actions: &[WorkflowRun],
+        split_runs: &SplitActionRuns,
     ) -> Result<(), Error> {
-        for run in actions {
+        if self.statuses_config.require_prior_success_to_retrigger {
+            if let Some(run) = split_runs
+                .failed
+                .iter()
+                .find(|run| !split_runs.succeeded.contains(&run.workflow_id))
+            {
+                return Err(Error::as_generic(format!(
+                    "Actions workflow '{}' has never succeeded, not re-running it",
+                    run.name
+                )));
+            }
+        }
+        for run in &split_runs.failed {
             warn!("Actions workflow '{}' failed, re-running it", run.name);
             self.github
                 .rerun_workflow(&pull_request.base.repo, run.id)
                 .await?;
+            self.retriggered
+                .push(format!("1 Actions workflow '{}'", run.name));
         }
         Ok(())
     }
 
+    /// Returns the pull request statuses should be checked on, pointing its head to the test
+    /// merge commit if configured to do so
+    fn status_pull_request(&self, pull_request: &PullRequest) -> Result<PullRequest, Error> {
+        if !self.statuses_config.check_merge_ref {
+            return Ok(pull_request.clone());
+        }
+        let merge_commit_sha = pull_request
+            .merge_commit_sha
+            .clone()
+            .ok_or_else(|| Error::transient_block("merge commit not computed yet"))?;
+        let mut pull_request = pull_request.clone();
+        pull_request.head.sha = merge_commit_sha;
+        // The statuses link points to the head so it can't be used
+        pull_request.links.statuses = None;
+        Ok(pull_request)
+    }
+
+    fn join_names(summaries: &[StatusSummary]) -> String {
+        let names: Vec<_> = summaries.iter().map(|s| s.name.as_str()).collect();
+        names.join(", ")
+    }
+
     fn parse_status_url(url: &str) -> Result<Url, Error> {
         let url = Url::parse(url)
             .map_err(|_| Error::as_generic(format!("invalid status target URL: {}", url)))?;
@@ -350,42 +1210,62 @@ impl CheckBuildFailed {
 struct StatusSummary {
     url: Url,
     name: String,
+    created_at: DateTime<Utc>,
 }
 
 struct StatusSummaries {
     pending: Vec<StatusSummary>,
     failed: Vec<StatusSummary>,
+    // The time at which every context that's currently green went green
+    green_since: HashMap<String, DateTime<Utc>>,
 }
 
 struct SplitActionRuns {
     pending: Vec<WorkflowRun>,
     failed: Vec<WorkflowRun>,
+    // The workflows that succeeded on any of the pull request's commits
+    succeeded: HashSet<u64>,
+}
+
+struct PriorSuccesses {
+    head_sha: String,
+    statuses: HashSet<String>,
 }
 
 #[async_trait]
 impl Step for CheckBuildFailed {
     async fn execute(&mut self, pull_request: &PullRequest) -> Result<StepStatus, Error> {
-        if !matches!(pull_request.mergeable_state, MergeableState::Blocked) {
-            return Ok(StepStatus::Passed);
+        // GitHub only blocks on the head's statuses, so a failure on the merge commit can't be
+        // spotted via the mergeable state
+        let blocked = matches!(pull_request.mergeable_state, MergeableState::Blocked);
+        let status_pull_request = self.status_pull_request(pull_request)?;
+        if !blocked && !self.statuses_config.check_merge_ref {
+            return self.check_green_stability(&status_pull_request).await;
         }
         if self.last_head_hash.as_ref() != Some(&pull_request.head.sha) {
-            if self.last_head_hash.is_some() {
+            if self.last_head_hash.is_some()
+                && self.statuses_config.failure_reset != FailureResetPolicy::Never
+            {
                 info!("Resetting failure counters as the head sha changed");
                 self.status_failures.clear();
             }
             self.last_head_hash = Some(pull_request.head.sha.clone());
         }
-        let statuses_result = self.check_statuses(pull_request).await?;
+        self.retriggered.clear();
+        let statuses_result = self.check_statuses(&status_pull_request).await?;
         let actions_result = self.check_actions(pull_request).await?;
-        if (statuses_result, actions_result) == (StepStatus::Passed, StepStatus::Passed)
-            && pull_request.mergeable_state == MergeableState::Blocked
-        {
+        if !self.retriggered.is_empty() {
+            info!("Re-triggered: {}", self.retriggered.join(", "));
+        }
+        if (statuses_result, actions_result) != (StepStatus::Passed, StepStatus::Passed) {
+            Ok(StepStatus::Waiting)
+        } else if blocked {
             // This means we don't currently support/know whatever led this PR to be unstable
-            return Err(Error::as_generic(
+            Err(Error::as_generic(
                 "pull request is blocked for unknown reasons",
-            ));
+            ))
         } else {
-            Ok(StepStatus::Waiting)
+            self.check_green_stability(&status_pull_request).await
         }
     }
 }
@@ -400,7 +1280,14 @@ impl fmt::Display for CheckBuildFailed {
 mod tests {
     use super::*;
     use crate::github::client::MockGithubClient;
-    use crate::github::{ActionRuns, NoBody, User, WorfklowRunStatus, WorkflowRun};
+    use crate::github::{
+        ActionRuns, BranchProtectionReviews, CheckRun, CheckRuns, CombinedStatus, Comment,
+        CommitDetails, Comparison, Deployment, DeploymentState, DeploymentStatus, Label, NoBody,
+        ProtectionSetting, PullRequestCommit, PullRequestFile, RepositoryInfo,
+        RequiredStatusChecks, Status, User, WorfklowRunStatus, WorkflowRun,
+    };
+    use crate::processing::{FailedWorkflow, FailedWorkflows};
+    use rstest::rstest;
     use std::future;
 
     struct WorkflowRunFixture {
@@ -426,13 +1313,18 @@ mod tests {
     }
 
     fn make_request_review(user: &str, state: ReviewState) -> PullRequestReview {
+        make_request_review_at(user, state, chrono::offset::Utc::now())
+    }
+
+    fn make_request_review_at(
+        user: &str,
+        state: ReviewState,
+        submitted_at: DateTime<Utc>,
+    ) -> PullRequestReview {
         PullRequestReview {
-            user: User {
-                login: user.into(),
-                ..Default::default()
-            },
+            user: User { login: user.into() },
             state,
-            submitted_at: chrono::offset::Local::now(),
+            submitted_at: Some(submitted_at),
         }
     }
 
@@ -455,11 +1347,49 @@ mod tests {
             ..Default::default()
         };
 
-        let step = CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new()).unwrap();
+        let mut step = CheckBuildFailed::new(
+            Arc::new(github),
+            vec![],
+            HashMap::new(),
+            StatusesConfig::default(),
+        )
+        .unwrap();
         let result = step.check_actions(&pull_request).await.unwrap();
         assert_eq!(result, StepStatus::Waiting);
     }
 
+    #[tokio::test]
+    async fn test_check_build_failed_actions_ignored() {
+        let fixture = make_workflow_run_fixture();
+        let action_runs = ActionRuns {
+            workflow_runs: vec![fixture.failed],
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_action_runs()
+            .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
+
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut step = CheckBuildFailed::new(
+            Arc::new(github),
+            vec![],
+            HashMap::new(),
+            StatusesConfig::default(),
+        )
+        .unwrap()
+        .with_ignored_actions(&["Some *".into()])
+        .unwrap();
+        let result = step.check_actions(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Passed);
+    }
+
     #[tokio::test]
     async fn test_check_build_failed_actions_failure() {
         let fixture = make_workflow_run_fixture();
@@ -484,9 +1414,1087 @@ mod tests {
             ..Default::default()
         };
 
-        let step = CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new()).unwrap();
+        let mut step = CheckBuildFailed::new(
+            Arc::new(github),
+            vec![],
+            HashMap::new(),
+            StatusesConfig::default(),
+        )
+        .unwrap();
         let result = step.check_actions(&pull_request).await.unwrap();
         assert_eq!(result, StepStatus::Waiting);
+        assert_eq!(step.retriggered, vec!["1 Actions workflow 'Some workflow'"]);
+    }
+
+    fn make_status(context: &str, state: StatusState) -> Status {
+        Status {
+            target_url: "https://example.com".into(),
+            state,
+            created_at: chrono::offset::Utc::now(),
+            context: context.into(),
+        }
+    }
+
+    fn make_checks_registered_github(
+        required: &[&str],
+        statuses: Vec<Status>,
+        check_runs: &[&str],
+    ) -> MockGithubClient {
+        let protection = BranchProtection {
+            required_status_checks: Some(RequiredStatusChecks {
+                contexts: required.iter().map(|c| c.to_string()).collect(),
+            }),
+            ..Default::default()
+        };
+        let check_runs = CheckRuns {
+            check_runs: check_runs
+                .iter()
+                .map(|name| CheckRun {
+                    name: name.to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        };
+        let combined_status = CombinedStatus {
+            state: StatusState::Pending,
+            total_count: statuses.len() as u32,
+        };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(move |_| Box::pin(future::ready(Ok(protection.clone()))));
+        github
+            .expect_combined_status()
+            .returning(move |_| Box::pin(future::ready(Ok(combined_status.clone()))));
+        github
+            .expect_pull_request_statuses()
+            .returning(move |_| Box::pin(future::ready(Ok(statuses.clone()))));
+        github
+            .expect_check_runs()
+            .returning(move |_| Box::pin(future::ready(Ok(check_runs.clone()))));
+        github
+    }
+
+    #[tokio::test]
+    async fn test_check_checks_registered_missing() {
+        let github = make_checks_registered_github(
+            &["ci/build", "lint"],
+            vec![make_status("ci/build", StatusState::Pending)],
+            &[],
+        );
+        let mut step = CheckChecksRegistered::new(Arc::new(github));
+        let result = step.execute(&PullRequest::default()).await;
+        assert!(matches!(
+            result,
+            Err(Error::PolicyBlock {
+                terminal: false,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_checks_registered_all_reported() {
+        let github = make_checks_registered_github(
+            &["ci/build", "lint"],
+            vec![make_status("ci/build", StatusState::Pending)],
+            &["lint"],
+        );
+        let mut step = CheckChecksRegistered::new(Arc::new(github));
+        let result = step.execute(&PullRequest::default()).await.unwrap();
+        assert_eq!(result, StepStatus::Passed);
+    }
+
+    fn make_failed_summary(name: &str, created_at: DateTime<Utc>) -> StatusSummary {
+        StatusSummary {
+            url: Url::parse("https://example.com").unwrap(),
+            name: name.into(),
+            created_at,
+        }
+    }
+
+    #[rstest]
+    #[case(Some("merge123".into()), StatusState::Success, StepStatus::Passed)]
+    #[case(Some("merge123".into()), StatusState::Pending, StepStatus::Waiting)]
+    #[case(None, StatusState::Success, StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_check_build_failed_merge_ref(
+        #[case] merge_commit_sha: Option<String>,
+        #[case] state: StatusState,
+        #[case] expected: StepStatus,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(|_| Box::pin(future::ready(Ok(BranchProtection::default()))));
+        let combined_state = state.clone();
+        github
+            .expect_combined_status()
+            .withf(|pull_request| pull_request.head.sha == "merge123")
+            .returning(move |_| {
+                let combined_status = CombinedStatus {
+                    state: combined_state.clone(),
+                    total_count: 1,
+                };
+                Box::pin(future::ready(Ok(combined_status)))
+            });
+        github
+            .expect_pull_request_statuses()
+            .withf(|pull_request| pull_request.head.sha == "merge123")
+            .returning(move |_| {
+                Box::pin(future::ready(Ok(vec![make_status("ci", state.clone())])))
+            });
+        github.expect_action_runs().returning(|_| {
+            let action_runs = ActionRuns {
+                workflow_runs: vec![],
+            };
+            Box::pin(future::ready(Ok(action_runs)))
+        });
+        let statuses_config = StatusesConfig {
+            check_merge_ref: true,
+            ..Default::default()
+        };
+        let mut step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), statuses_config)
+                .unwrap();
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Clean,
+            merge_commit_sha,
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let status = match step.execute(&pull_request).await {
+            Err(Error::PolicyBlock {
+                terminal: false, ..
+            }) => StepStatus::Waiting,
+            other => other.unwrap(),
+        };
+        assert_eq!(status, expected);
+    }
+
+    fn make_max_failures_step(failure_reset: FailureResetPolicy) -> CheckBuildFailed {
+        let mut failures_config = HashMap::new();
+        failures_config.insert("ci".into(), StatusFailuresConfig { max_failures: 2 });
+        let statuses_config = StatusesConfig {
+            failure_reset,
+            failure_window_seconds: 3600,
+            ..Default::default()
+        };
+        CheckBuildFailed::new(
+            Arc::new(MockGithubClient::default()),
+            vec![],
+            failures_config,
+            statuses_config,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_max_failures() {
+        let mut step = make_max_failures_step(FailureResetPolicy::OnHeadChange);
+        let old = Utc::now() - Duration::hours(3);
+        assert!(step
+            .check_max_failures(&[make_failed_summary("ci", old)])
+            .is_ok());
+        assert!(step
+            .check_max_failures(&[make_failed_summary("ci", Utc::now())])
+            .is_err());
+    }
+
+    struct RoutedOnlyRunner;
+
+    #[async_trait]
+    impl WorkflowRunner for RoutedOnlyRunner {
+        fn workflow_kind(&self) -> &'static str {
+            "test workflow"
+        }
+
+        async fn find_failed_workflows(
+            &self,
+            job_urls: &[Url],
+            routed: bool,
+        ) -> Result<FailedWorkflows, Error> {
+            let mut workflows = FailedWorkflows::default();
+            if routed {
+                workflows.rerunnable.push(FailedWorkflow {
+                    id: "1".into(),
+                    name: "test".into(),
+                    job_url: job_urls[0].clone(),
+                });
+            }
+            Ok(workflows)
+        }
+
+        async fn rerun_workflow(&self, _workflow: &FailedWorkflow) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_process_failed_statuses_routed() {
+        let runner: Arc<dyn WorkflowRunner> = Arc::new(RoutedOnlyRunner);
+        let make_step = || {
+            CheckBuildFailed::new(
+                Arc::new(MockGithubClient::default()),
+                vec![runner.clone()],
+                HashMap::new(),
+                StatusesConfig::default(),
+            )
+            .unwrap()
+        };
+
+        let mut step = make_step();
+        let failed = vec![make_failed_summary("ci", Utc::now())];
+        let pull_request = PullRequest::default();
+        assert!(step
+            .process_failed_statuses(&pull_request, failed)
+            .await
+            .is_err());
+
+        let mut status_runners = HashMap::new();
+        status_runners.insert("ci".to_string(), runner.clone());
+        let mut step = make_step().with_status_runners(status_runners);
+        let failed = vec![make_failed_summary("ci", Utc::now())];
+        step.process_failed_statuses(&pull_request, failed)
+            .await
+            .unwrap();
+        assert_eq!(step.retriggered, vec!["1 test workflow"]);
+    }
+
+    #[rstest]
+    #[case::failed_everywhere("ci", false)]
+    #[case::status_succeeded_on_older_commit("lint", true)]
+    #[case::unknown("build", false)]
+    #[tokio::test]
+    async fn test_check_prior_success(#[case] name: &str, #[case] expected: bool) {
+        let statuses_config = StatusesConfig {
+            require_prior_success_to_retrigger: true,
+            ..Default::default()
+        };
+        let mut github = MockGithubClient::default();
+        // Loaded once per head
+        github
+            .expect_pull_request_commits()
+            .times(2)
+            .returning(|_| {
+                let commits = ["old", "head"]
+                    .iter()
+                    .map(|sha| PullRequestCommit {
+                        sha: sha.to_string(),
+                        ..Default::default()
+                    })
+                    .collect();
+                Box::pin(future::ready(Ok(commits)))
+            });
+        github.expect_commit_statuses().returning(|_, sha| {
+            let make_status = |context: &str, state| Status {
+                target_url: "https://example.com".into(),
+                state,
+                created_at: Utc::now(),
+                context: context.into(),
+            };
+            let statuses = match sha {
+                "old" => vec![
+                    make_status("ci", StatusState::Failure),
+                    make_status("lint", StatusState::Success),
+                ],
+                _ => vec![
+                    make_status("ci", StatusState::Failure),
+                    make_status("lint", StatusState::Failure),
+                ],
+            };
+            Box::pin(future::ready(Ok(statuses)))
+        });
+        let mut step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), statuses_config)
+                .unwrap();
+        let failed = [make_failed_summary(name, Utc::now())];
+        let mut pull_request = PullRequest::default();
+        for sha in ["head", "head", "new-head"] {
+            pull_request.head.sha = sha.into();
+            let result = step.check_prior_success(&pull_request, &failed).await;
+            assert_eq!(result.is_ok(), expected);
+        }
+    }
+
+    #[rstest]
+    #[case::succeeded_before(true)]
+    #[case::never_succeeded(false)]
+    #[tokio::test]
+    async fn test_check_build_failed_actions_prior_success(#[case] succeeded_before: bool) {
+        let fixture = make_workflow_run_fixture();
+        let mut workflow_runs = vec![fixture.failed.clone()];
+        if succeeded_before {
+            workflow_runs.push(WorkflowRun {
+                id: 2,
+                head_sha: "oldsha".into(),
+                conclusion: Some(WorkflowRunConclusion::Success),
+                ..fixture.failed
+            });
+        }
+        let action_runs = ActionRuns { workflow_runs };
+        let mut github = MockGithubClient::default();
+        github
+            .expect_action_runs()
+            .returning(move |_| Box::pin(future::ready(Ok(action_runs.clone()))));
+        github
+            .expect_rerun_workflow()
+            .times(succeeded_before as usize)
+            .returning(move |_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let statuses_config = StatusesConfig {
+            require_prior_success_to_retrigger: true,
+            ..Default::default()
+        };
+        let mut step =
+            CheckBuildFailed::new(Arc::new(github), vec![], HashMap::new(), statuses_config)
+                .unwrap();
+        let pull_request = PullRequest {
+            head: Branch {
+                sha: "mysha".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let result = step.check_actions(&pull_request).await;
+        assert_eq!(result.is_ok(), succeeded_before);
+    }
+
+    #[test]
+    fn test_check_max_failures_time_decay() {
+        let mut step = make_max_failures_step(FailureResetPolicy::TimeDecay);
+        let old = Utc::now() - Duration::hours(3);
+        assert!(step
+            .check_max_failures(&[make_failed_summary("ci", old)])
+            .is_ok());
+        assert!(step
+            .check_max_failures(&[make_failed_summary("ci", Utc::now())])
+            .is_ok());
+        assert!(step
+            .check_max_failures(&[make_failed_summary("ci", Utc::now())])
+            .is_err());
+    }
+
+    #[rstest]
+    #[case::not_started(vec![], &[], false)]
+    #[case::check_run(vec![], &["ci/build"], true)]
+    #[case::status(vec![make_status("ci/build", StatusState::Pending)], &[], true)]
+    #[tokio::test]
+    async fn test_check_checks_registered_started(
+        #[case] statuses: Vec<Status>,
+        #[case] check_runs: &[&str],
+        #[case] passes: bool,
+    ) {
+        let github = make_checks_registered_github(&["ci/build"], statuses, check_runs);
+        let mut step = CheckChecksRegistered::new(Arc::new(github));
+        let result = step.execute(&PullRequest::default()).await;
+        assert_eq!(matches!(result, Ok(StepStatus::Passed)), passes);
+    }
+
+    #[rstest]
+    #[case::none_reported(StatusState::Pending, 0, StepStatus::Passed)]
+    #[case::succeeded(StatusState::Success, 2, StepStatus::Passed)]
+    #[case::pending(StatusState::Pending, 2, StepStatus::Waiting)]
+    #[tokio::test]
+    async fn test_check_build_failed_combined_status(
+        #[case] state: StatusState,
+        #[case] total_count: u32,
+        #[case] expected: StepStatus,
+    ) {
+        let needs_statuses = expected == StepStatus::Waiting;
+        let mut github = MockGithubClient::default();
+        github.expect_combined_status().returning(move |_| {
+            let combined_status = CombinedStatus {
+                state: state.clone(),
+                total_count,
+            };
+            Box::pin(future::ready(Ok(combined_status)))
+        });
+        github
+            .expect_pull_request_statuses()
+            .times(needs_statuses as usize)
+            .returning(|_| {
+                let statuses = vec![make_status("ci", StatusState::Pending)];
+                Box::pin(future::ready(Ok(statuses)))
+            });
+        let mut step = CheckBuildFailed::new(
+            Arc::new(github),
+            vec![],
+            HashMap::new(),
+            StatusesConfig::default(),
+        )
+        .unwrap();
+        let result = step.check_statuses(&PullRequest::default()).await.unwrap();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_check_green_stability() {
+        let now = Utc::now();
+        let statuses = vec![
+            Status {
+                created_at: now - Duration::seconds(10),
+                ..make_status("ci", StatusState::Success)
+            },
+            Status {
+                created_at: now - Duration::hours(1),
+                ..make_status("ci", StatusState::Success)
+            },
+            Status {
+                created_at: now - Duration::hours(2),
+                ..make_status("ci", StatusState::Failure)
+            },
+        ];
+        let mut github = MockGithubClient::default();
+        github
+            .expect_pull_request_statuses()
+            .returning(move |_| Box::pin(future::ready(Ok(statuses.clone()))));
+        let make_step = |github, green_stability_seconds| {
+            let statuses_config = StatusesConfig {
+                green_stability_seconds,
+                ..Default::default()
+            };
+            CheckBuildFailed::new(github, vec![], HashMap::new(), statuses_config).unwrap()
+        };
+        let github = Arc::new(github);
+        let pull_request = PullRequest::default();
+
+        let step = make_step(github.clone(), 60);
+        let result = step.check_green_stability(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Passed);
+
+        let step = make_step(github, 2 * 3600);
+        let result = step.check_green_stability(&pull_request).await.unwrap();
+        assert_eq!(result, StepStatus::Waiting);
+    }
+
+    #[tokio::test]
+    async fn test_check_task_list() {
+        let body = "Checklist:\n- [x] Design doc\n  * [X] Tests\n- [ ] Docs\n- not a task";
+        let pull_request = PullRequest {
+            body: Some(body.into()),
+            ..Default::default()
+        };
+        let mut step = CheckTaskList::new(TaskListConfig {
+            require_all_checked: false,
+            min_checked: 2,
+        });
+        assert_eq!(step.count_items(body), (2, 1));
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+
+        let mut step = CheckTaskList::new(TaskListConfig {
+            require_all_checked: true,
+            min_checked: 0,
+        });
+        assert!(step.execute(&pull_request).await.is_err());
+
+        let mut step = CheckTaskList::new(TaskListConfig {
+            require_all_checked: false,
+            min_checked: 3,
+        });
+        assert!(step.execute(&pull_request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_merge_command() {
+        let comments = vec![
+            Comment {
+                user: User {
+                    login: "random".into(),
+                },
+                body: "/mergebro merge".into(),
+                ..Default::default()
+            },
+            Comment {
+                user: User {
+                    login: "boss".into(),
+                },
+                body: "looks good".into(),
+                ..Default::default()
+            },
+        ];
+        let mut github = MockGithubClient::default();
+        github
+            .expect_list_comments()
+            .times(2)
+            .returning(move |_| Box::pin(future::ready(Ok(comments.clone()))));
+        let config = CommandConfig {
+            required: true,
+            authorized_users: vec!["boss".into()],
+            ..Default::default()
+        };
+        let github = Arc::new(github);
+        let mut step = CheckMergeCommand::new(github.clone(), config.clone());
+        assert!(step.execute(&PullRequest::default()).await.is_err());
+
+        let config = CommandConfig {
+            authorized_users: vec!["random".into()],
+            ..config
+        };
+        let mut step = CheckMergeCommand::new(github, config);
+        assert_eq!(
+            step.execute(&PullRequest::default()).await.unwrap(),
+            StepStatus::Passed
+        );
+        // Once triggered, comments aren't fetched anymore
+        assert_eq!(
+            step.execute(&PullRequest::default()).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[rstest]
+    #[case("exit 0", Some(StepStatus::Passed))]
+    #[case("exit 75", Some(StepStatus::Waiting))]
+    #[case("echo nope >&2; exit 1", None)]
+    #[case(r#"test "$MERGEBRO_PR_NUMBER" = 1337"#, Some(StepStatus::Passed))]
+    #[tokio::test]
+    async fn test_check_external_command(
+        #[case] command: &str,
+        #[case] expected: Option<StepStatus>,
+    ) {
+        let mut step = CheckExternalCommand::new(ExternalCommandConfig {
+            name: "test".into(),
+            command: command.into(),
+            waiting_exit_code: 75,
+            timeout_seconds: 10,
+        });
+        let pull_request = PullRequest {
+            number: 1337,
+            ..Default::default()
+        };
+        let result = step.execute(&pull_request).await.ok();
+        assert_eq!(result, expected);
+    }
+
+    #[tokio::test]
+    async fn test_check_external_command_dry_run() {
+        let mut step = CheckExternalCommand::new(ExternalCommandConfig {
+            name: "test".into(),
+            command: "exit 1".into(),
+            waiting_exit_code: 75,
+            timeout_seconds: 10,
+        })
+        .with_dry_run(true);
+        assert_eq!(
+            step.execute(&PullRequest::default()).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_external_command_timeout() {
+        let mut step = CheckExternalCommand::new(ExternalCommandConfig {
+            name: "test".into(),
+            command: "sleep 30".into(),
+            waiting_exit_code: 75,
+            timeout_seconds: 1,
+        });
+        let start = std::time::Instant::now();
+        assert!(step.execute(&PullRequest::default()).await.is_err());
+        assert!(start.elapsed().as_secs() < 10);
+    }
+
+    #[tokio::test]
+    async fn test_check_sensitive_base() {
+        let mut step = CheckSensitiveBase::new(&["release/*".into()]).unwrap();
+        let mut pull_request = PullRequest {
+            base: Branch {
+                name: "release/1.0".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(step.execute(&pull_request).await.is_err());
+
+        pull_request.base.name = "main".into();
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    fn make_version_bump_config(version_files: Vec<String>) -> VersionBumpConfig {
+        VersionBumpConfig {
+            pattern: Regex::new(r"^\[(\w+)\]").unwrap(),
+            label_prefix: Some("bump:".into()),
+            allowed: vec!["major".into(), "minor".into(), "patch".into()],
+            version_files,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_version_bump() {
+        let config = make_version_bump_config(vec![]);
+        let mut step = CheckVersionBump::new(Arc::new(MockGithubClient::default()), config);
+        let mut pull_request = PullRequest {
+            title: "[minor] Add potato smasher".into(),
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+
+        pull_request.title = "[huge] Add potato smasher".into();
+        assert!(matches!(
+            step.execute(&pull_request).await,
+            Err(Error::PolicyBlock {
+                terminal: false,
+                ..
+            })
+        ));
+
+        pull_request.title = "Add potato smasher".into();
+        assert!(matches!(
+            step.execute(&pull_request).await,
+            Err(Error::PolicyBlock {
+                terminal: false,
+                ..
+            })
+        ));
+
+        pull_request.labels = vec![Label {
+            name: "bump:patch".into(),
+        }];
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+
+        // An unknown bump in the title falls through to the labels
+        pull_request.title = "[WIP] Add potato smasher".into();
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_version_bump_files() {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_files().returning(|_| {
+            let files = vec![PullRequestFile {
+                filename: "src/lib.rs".into(),
+            }];
+            Box::pin(future::ready(Ok(files)))
+        });
+        let config = make_version_bump_config(vec!["Cargo.toml".into()]);
+        let mut step = CheckVersionBump::new(Arc::new(github), config);
+        let pull_request = PullRequest {
+            title: "[major] Remove potato smasher".into(),
+            ..Default::default()
+        };
+        assert!(step.execute(&pull_request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_check_commit_messages() {
+        let mut github = MockGithubClient::default();
+        github.expect_pull_request_commits().returning(|_| {
+            let commits = ["feat: add smasher", "oops\n\nfix typo"]
+                .iter()
+                .enumerate()
+                .map(|(index, message)| PullRequestCommit {
+                    sha: format!("{}abcdef0123", index),
+                    commit: CommitDetails {
+                        message: message.to_string(),
+                    },
+                })
+                .collect();
+            Box::pin(future::ready(Ok(commits)))
+        });
+        let config = CommitMessagesConfig {
+            pattern: "^(feat|fix): ".into(),
+            only_preserving_methods: false,
+        };
+        let mut step =
+            CheckCommitMessages::new(Arc::new(github), config, &MergeMethod::Merge).unwrap();
+        let error = step
+            .execute(&PullRequest::default())
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("1abcdef (oops)"));
+        assert!(!error.contains("0abcdef"));
+    }
+
+    #[rstest]
+    #[case(true, true)]
+    #[case(false, false)]
+    #[tokio::test]
+    async fn test_check_commit_messages_squash(
+        #[case] allow_squash_merge: bool,
+        #[case] expected_pass: bool,
+    ) {
+        let mut github = MockGithubClient::default();
+        github.expect_repo_info().returning(move |_, _| {
+            let info = RepositoryInfo {
+                allow_squash_merge,
+                allow_merge_commit: true,
+                ..Default::default()
+            };
+            Box::pin(future::ready(Ok(info)))
+        });
+        github.expect_pull_request_commits().returning(|_| {
+            let commits = vec![PullRequestCommit {
+                sha: "abcdef0123".into(),
+                commit: CommitDetails {
+                    message: "oops".into(),
+                },
+            }];
+            Box::pin(future::ready(Ok(commits)))
+        });
+        let config = CommitMessagesConfig {
+            pattern: "^(feat|fix): ".into(),
+            only_preserving_methods: true,
+        };
+        let mut step =
+            CheckCommitMessages::new(Arc::new(github), config, &MergeMethod::Squash).unwrap();
+        let result = step.execute(&PullRequest::default()).await;
+        if expected_pass {
+            assert_eq!(result.unwrap(), StepStatus::Passed);
+        } else {
+            assert!(matches!(
+                result,
+                Err(Error::PolicyBlock { terminal: true, .. })
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_current_state_draft() {
+        let pull_request = PullRequest {
+            state: PullRequestState::Open,
+            draft: true,
+            ..Default::default()
+        };
+        let mut step = CheckCurrentStateStep::default();
+        assert!(step.execute(&pull_request).await.is_err());
+
+        let mut step = CheckCurrentStateStep::new(CurrentStateConfig {
+            mark_ready_when_passed: true,
+        });
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_current_state_archived() {
+        let mut pull_request = PullRequest {
+            state: PullRequestState::Open,
+            ..Default::default()
+        };
+        pull_request.base.repo.archived = true;
+        let mut step = CheckCurrentStateStep::default();
+        assert!(step.execute(&pull_request).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mark_ready_for_review() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_mark_ready_for_review()
+            .times(1)
+            .returning(|_| Box::pin(future::ready(Ok(()))));
+        let mut step = MarkReadyForReview::new(Arc::new(github));
+        let mut pull_request = PullRequest {
+            draft: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Waiting
+        );
+
+        pull_request.draft = false;
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::Passed
+        );
+    }
+
+    #[rstest]
+    #[case(Some(DeploymentState::InProgress), false)]
+    #[case(Some(DeploymentState::Queued), false)]
+    #[case(Some(DeploymentState::Success), true)]
+    #[case(None, true)]
+    #[tokio::test]
+    async fn test_check_no_active_deployment(
+        #[case] state: Option<DeploymentState>,
+        #[case] passes: bool,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_deployments()
+            .withf(|_, environment| environment == "production")
+            .returning(|_, _| {
+                let deployment = Deployment {
+                    id: 42,
+                    ..Default::default()
+                };
+                Box::pin(future::ready(Ok(vec![deployment])))
+            });
+        github
+            .expect_deployment_statuses()
+            .withf(|_, id| *id == 42)
+            .returning(move |_, _| {
+                let statuses = state
+                    .clone()
+                    .map(|state| DeploymentStatus { state })
+                    .into_iter()
+                    .collect();
+                Box::pin(future::ready(Ok(statuses)))
+            });
+        let config = DeploymentConfig {
+            environment: "production".into(),
+        };
+        let mut step = CheckNoActiveDeployment::new(Arc::new(github), config);
+        let result = step.execute(&PullRequest::default()).await;
+        assert_eq!(matches!(result, Ok(StepStatus::Passed)), passes);
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_compare_ref() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_compare_commits()
+            .withf(|_, base, head| base == "integration" && head == "mysha")
+            .returning(|_, _, _| {
+                let comparison = Comparison {
+                    behind_by: 2,
+                    ..Default::default()
+                };
+                Box::pin(future::ready(Ok(comparison)))
+            });
+        github
+            .expect_merge_into_branch()
+            .withf(|branch, head| branch.name == "feature" && head == "integration")
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let config = BehindConfig {
+            compare_ref: Some("integration".into()),
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), config);
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Clean,
+            head: Branch {
+                sha: "mysha".into(),
+                name: "feature".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::BranchUpdated
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_compare_ref_fork() {
+        let mut github = MockGithubClient::default();
+        github.expect_compare_commits().returning(|_, _, _| {
+            let comparison = Comparison {
+                behind_by: 2,
+                ..Default::default()
+            };
+            Box::pin(future::ready(Ok(comparison)))
+        });
+        github.expect_merge_into_branch().never();
+        let config = BehindConfig {
+            compare_ref: Some("integration".into()),
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), config);
+        let mut pull_request = PullRequest {
+            mergeable_state: MergeableState::Clean,
+            ..Default::default()
+        };
+        pull_request.base.repo.full_name = "potato/smasher".into();
+        pull_request.head.repo.full_name = "tomato/smasher".into();
+        let result = step.execute(&pull_request).await;
+        assert!(matches!(
+            result,
+            Err(Error::PolicyBlock { terminal: true, .. })
+        ));
+    }
+
+    #[rstest]
+    #[case(Some(false), UpdateBranchMethod::Merge)]
+    #[case(Some(true), UpdateBranchMethod::Rebase)]
+    #[case(None, UpdateBranchMethod::Merge)]
+    #[tokio::test]
+    async fn test_check_behind_master_update_method(
+        #[case] linear_history: Option<bool>,
+        #[case] expected: UpdateBranchMethod,
+    ) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(move |_| match linear_history {
+                Some(enabled) => {
+                    let protection = BranchProtection {
+                        required_linear_history: ProtectionSetting { enabled },
+                        ..Default::default()
+                    };
+                    Box::pin(future::ready(Ok(protection)))
+                }
+                None => Box::pin(future::ready(Err(crate::client::Error::Http {
+                    status: reqwest::StatusCode::NOT_FOUND,
+                    message: "Branch not protected".into(),
+                }))),
+            });
+        github
+            .expect_update_branch()
+            .withf(move |_, method| *method == expected)
+            .times(1)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let mut step = CheckBehindMaster::new(Arc::new(github), BehindConfig::default());
+        let pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            ..Default::default()
+        };
+        assert_eq!(
+            step.execute(&pull_request).await.unwrap(),
+            StepStatus::BranchUpdated
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_update_attempts_survive_unknown_state() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(|_| Box::pin(future::ready(Ok(BranchProtection::default()))));
+        github
+            .expect_update_branch()
+            .times(2)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let config = BehindConfig {
+            max_update_attempts: Some(2),
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), config);
+        let mut pull_request = PullRequest::default();
+        for sha in ["a", "b"] {
+            pull_request.head.sha = sha.into();
+            pull_request.mergeable_state = MergeableState::Behind;
+            assert_eq!(
+                step.execute(&pull_request).await.unwrap(),
+                StepStatus::BranchUpdated
+            );
+            // GitHub recomputes mergeability after the update lands
+            pull_request.mergeable_state = MergeableState::Unknown;
+            assert_eq!(
+                step.execute(&pull_request).await.unwrap(),
+                StepStatus::Passed
+            );
+        }
+        pull_request.head.sha = "c".into();
+        pull_request.mergeable_state = MergeableState::Behind;
+        assert!(matches!(
+            step.execute(&pull_request).await,
+            Err(Error::PolicyBlock { terminal: true, .. })
+        ));
+
+        // Catching up resets the attempts
+        pull_request.mergeable_state = MergeableState::Clean;
+        step.execute(&pull_request).await.unwrap();
+        assert_eq!(step.update_attempts, 0);
+    }
+
+    #[tokio::test]
+    async fn test_check_behind_master_max_update_attempts() {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(|_| Box::pin(future::ready(Ok(BranchProtection::default()))));
+        github
+            .expect_update_branch()
+            .times(2)
+            .returning(|_, _| Box::pin(future::ready(Ok(NoBody {}))));
+        let config = BehindConfig {
+            max_update_attempts: Some(2),
+            ..Default::default()
+        };
+        let mut step = CheckBehindMaster::new(Arc::new(github), config);
+        let mut pull_request = PullRequest {
+            mergeable_state: MergeableState::Behind,
+            ..Default::default()
+        };
+        for (sha, expected) in [
+            ("a", StepStatus::BranchUpdated),
+            // The update didn't land yet
+            ("a", StepStatus::Waiting),
+            ("b", StepStatus::BranchUpdated),
+        ] {
+            pull_request.head.sha = sha.into();
+            assert_eq!(step.execute(&pull_request).await.unwrap(), expected);
+        }
+        pull_request.head.sha = "c".into();
+        assert!(matches!(
+            step.execute(&pull_request).await,
+            Err(Error::PolicyBlock { terminal: true, .. })
+        ));
+    }
+
+    #[rstest]
+    #[case(ReviewsMode::Max, Some(3), 3)]
+    #[case(ReviewsMode::Max, Some(1), 2)]
+    #[case(ReviewsMode::Max, None, 2)]
+    #[case(ReviewsMode::BranchProtectionOnly, Some(1), 1)]
+    #[case(ReviewsMode::BranchProtectionOnly, None, 0)]
+    #[case(ReviewsMode::ConfigOnly, Some(3), 2)]
+    #[case(ReviewsMode::ConfigOnly, None, 2)]
+    fn test_check_reviews_required_approvals(
+        #[case] mode: ReviewsMode,
+        #[case] protection_approvals: Option<u32>,
+        #[case] expected: u32,
+    ) {
+        let config = ReviewsConfig {
+            approvals: 2,
+            mode,
+            abort_on_shortfall: false,
+        };
+        let step = CheckReviewsStep::new(Arc::new(MockGithubClient::default()), config).unwrap();
+        let branch_protection = protection_approvals.map(|approvals| BranchProtection {
+            reviews: BranchProtectionReviews { approvals },
+            ..Default::default()
+        });
+        assert_eq!(step.required_approvals(branch_protection), expected);
+    }
+
+    #[rstest]
+    #[case(false)]
+    #[case(true)]
+    #[tokio::test]
+    async fn test_check_reviews_shortfall(#[case] abort_on_shortfall: bool) {
+        let mut github = MockGithubClient::default();
+        github
+            .expect_branch_protection()
+            .returning(|_| Box::pin(future::ready(Ok(BranchProtection::default()))));
+        github
+            .expect_pull_request_reviews()
+            .returning(|_| Box::pin(future::ready(Ok(Vec::new()))));
+        let config = ReviewsConfig {
+            approvals: 1,
+            mode: ReviewsMode::ConfigOnly,
+            abort_on_shortfall,
+        };
+        let mut step = CheckReviewsStep::new(Arc::new(github), config).unwrap();
+        let result = step.execute(&PullRequest::default()).await;
+        assert!(
+            matches!(result, Err(Error::PolicyBlock { terminal, .. }) if terminal == abort_on_shortfall)
+        );
     }
 
     #[test]
@@ -527,4 +2535,54 @@ mod tests {
             ])
         );
     }
+
+    #[test]
+    fn test_check_reviews_compute_approvals_out_of_order() {
+        let now = Utc::now();
+        assert_eq!(
+            1,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review_at("bob", ReviewState::Approved, now),
+                make_request_review_at("bob", ReviewState::Dismissed, now - Duration::hours(1)),
+                make_request_review_at("bob", ReviewState::Approved, now - Duration::hours(2)),
+            ])
+        );
+        assert_eq!(
+            0,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review_at("bob", ReviewState::ChangesRequested, now),
+                make_request_review_at("bob", ReviewState::Approved, now - Duration::hours(1)),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_check_reviews_compute_approvals_comment_after_approval() {
+        let now = Utc::now();
+        assert_eq!(
+            1,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review_at("bob", ReviewState::Approved, now - Duration::hours(1)),
+                make_request_review_at("bob", ReviewState::Commented, now),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_check_reviews_compute_approvals_pending_after_approval() {
+        let pending = PullRequestReview {
+            user: User {
+                login: "bob".into(),
+            },
+            state: ReviewState::Pending,
+            submitted_at: None,
+        };
+        assert_eq!(
+            1,
+            CheckReviewsStep::compute_approvals(&[
+                make_request_review("bob", ReviewState::Approved),
+                pending,
+            ])
+        );
+    }
 }